@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+/// Exponential backoff with jitter for retrying failed requests
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            attempt: 0,
+        }
+    }
+
+    /// Number of retries since the last success
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Delay before the next retry. Doubles on every call up to `max`, then
+    /// a random half of it is taken off so that clients don't retry in lockstep.
+    pub fn next_delay(&mut self) -> Duration {
+        let exp = self.base.saturating_mul(1u32 << self.attempt.min(16));
+        let capped = exp.min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        capped.mul_f64(0.5 + fastrand::f64() * 0.5)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_the_cap_with_jitter() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+        for (attempt, full_ms) in [100, 200, 400, 800, 1000, 1000, 1000]
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(backoff.attempts(), attempt as u32);
+            let delay = backoff.next_delay();
+            let full = Duration::from_millis(full_ms);
+            assert!(
+                delay >= full / 2 && delay <= full,
+                "attempt {}: {:?}",
+                attempt,
+                delay
+            );
+        }
+
+        backoff.reset();
+        assert_eq!(backoff.attempts(), 0);
+        assert!(backoff.next_delay() <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn many_attempts_stay_at_the_cap() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+        for _ in 0..100 {
+            backoff.next_delay();
+        }
+        assert!(backoff.next_delay() <= Duration::from_secs(60));
+    }
+}
@@ -1,31 +1,179 @@
+use std::io::Write;
+
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+
+use youtube_live_tts::{config, recorder::Recorder, target::Target, youtube};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Id,
+    Timestamp,
+    Source,
+    Author,
+    Roles,
+    Amount,
+    Text,
+}
+
+impl Field {
+    fn name(&self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::Timestamp => "timestamp",
+            Field::Source => "source",
+            Field::Author => "author",
+            Field::Roles => "roles",
+            Field::Amount => "amount",
+            Field::Text => "text",
+        }
+    }
 
-use youtube_live_tts::{config, youtube};
+    fn value(&self, message: &youtube::ChatMessage) -> String {
+        match self {
+            Field::Id => message.id.clone(),
+            Field::Timestamp => message.timestamp.clone(),
+            Field::Source => message.source.clone(),
+            Field::Author => message.author.clone(),
+            Field::Roles => message.roles.join(" "),
+            Field::Amount => message.amount.clone().unwrap_or_default(),
+            Field::Text => message.text.clone(),
+        }
+    }
+
+    fn json_value(&self, message: &youtube::ChatMessage) -> serde_json::Value {
+        match self {
+            Field::Roles => serde_json::json!(message.roles),
+            Field::Amount => serde_json::json!(message.amount),
+            _ => serde_json::Value::String(self.value(message)),
+        }
+    }
+}
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about = "Simple YouTube chat monitor")]
 struct Args {
-    /// YouTube Live video ID
+    /// YouTube Live video ID or URL
     #[clap(short, long, group = "target")]
     video_id: Option<String>,
 
-    /// YouTube channel ID or username
+    /// YouTube channel ID, @handle, username or URL
     #[clap(short, long, group = "target")]
     channel_id: Option<String>,
 
     /// Path to config file (optional)
     #[clap(short, long)]
     config: Option<String>,
+
+    /// Append raw chat items, messages and events to a JSON-lines file
+    #[clap(long)]
+    record: Option<String>,
+
+    /// Output format
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Comma-separated fields to print
+    #[clap(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "timestamp,author,text"
+    )]
+    fields: Vec<Field>,
+
+    /// Only print messages the bot would read aloud under the config's filters
+    #[clap(long)]
+    filter: bool,
+
+    /// Stream label, selecting its [sources.<label>] settings
+    #[clap(long, default_value = "")]
+    label: String,
+}
+
+// Quote a CSV value when it contains a separator, quote or line break
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// TSV has no quoting, so separators inside values become spaces
+fn tsv_escape(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+fn header(format: Format, fields: &[Field]) -> Option<String> {
+    let names = fields.iter().map(Field::name);
+    match format {
+        Format::Csv => Some(names.collect::<Vec<_>>().join(",")),
+        Format::Tsv => Some(names.collect::<Vec<_>>().join("\t")),
+        Format::Text | Format::Json => None,
+    }
+}
+
+fn format_message(format: Format, fields: &[Field], message: &youtube::ChatMessage) -> String {
+    match format {
+        // The classic `[timestamp] author: text` layout, with any other
+        // selected fields in between
+        Format::Text => {
+            let mut line = String::new();
+            for field in fields {
+                let value = field.value(message);
+                match field {
+                    Field::Text => continue,
+                    _ if value.is_empty() => continue,
+                    Field::Timestamp | Field::Source => line.push_str(&format!("[{}] ", value)),
+                    Field::Roles => line.push_str(&format!("({}) ", value)),
+                    Field::Amount => line.push_str(&format!("{{{}}} ", value)),
+                    Field::Id | Field::Author => line.push_str(&format!("{} ", value)),
+                }
+            }
+            let line = line.trim_end();
+            if fields.contains(&Field::Text) {
+                format!("{}: {}", line, message.text)
+            } else {
+                line.to_string()
+            }
+        }
+        Format::Json => {
+            let object: serde_json::Map<_, _> = fields
+                .iter()
+                .map(|field| (field.name().to_string(), field.json_value(message)))
+                .collect();
+            serde_json::Value::Object(object).to_string()
+        }
+        Format::Csv => fields
+            .iter()
+            .map(|field| csv_escape(&field.value(message)))
+            .collect::<Vec<_>>()
+            .join(","),
+        Format::Tsv => fields
+            .iter()
+            .map(|field| tsv_escape(&field.value(message)))
+            .collect::<Vec<_>>()
+            .join("\t"),
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
+    // Initialize logging on stderr, so stdout carries only the records
     tracing_subscriber::fmt()
         .with_env_filter(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "youtube_live_tts=debug".into()),
         )
+        .with_writer(std::io::stderr)
         .init();
 
     // Parse command line arguments
@@ -34,19 +182,19 @@ async fn main() -> Result<()> {
 
     // Load configuration
     let config = config::load_config(args.config.as_deref())?;
+    if config.api_key.is_empty() {
+        return Err(anyhow::anyhow!(
+            "api_key is required to read YouTube chats. Set it in the config file or YTTTS_API_KEY"
+        ));
+    }
 
     // Get video ID either directly or by finding the live stream for a channel
-    let video_id = match (args.video_id, args.channel_id) {
-        (Some(vid), _) => {
-            tracing::info!("Using provided video ID: {}", vid);
-            vid
-        }
-        (_, Some(channel)) => {
-            tracing::info!("Searching for live stream for channel: {}", channel);
-            let client = reqwest::Client::new();
-            youtube::ChatMonitor::find_live_video_id_by_channel(&client, &channel, &config.api_key)
-                .await?
-        }
+    let quota = config.quota_tracker();
+
+    // Both arguments also accept URLs and @handles
+    let target = match (args.video_id, args.channel_id) {
+        (Some(vid), _) => Target::from_video_arg(&vid),
+        (_, Some(channel)) => Target::from_channel_arg(&channel),
         _ => {
             return Err(anyhow::anyhow!(
                 "Either --video-id or --channel-id must be provided"
@@ -54,20 +202,66 @@ async fn main() -> Result<()> {
         }
     };
 
+    let video_id = match &target {
+        Target::Video(vid) => {
+            tracing::info!("Using provided video ID: {}", vid);
+            vid.clone()
+        }
+        _ => {
+            tracing::info!("Searching for live stream for channel: {:?}", target);
+            let client = reqwest::Client::new();
+            youtube::ChatMonitor::resolve_video_id(&client, &target, &config.api_key, Some(&quota))
+                .await?
+        }
+    };
+
     // Start chat monitor
     let mut chat_monitor = youtube::ChatMonitor::new(&video_id, &config.api_key)?;
     chat_monitor.set_poll_interval(config.poll_interval_ms);
+    chat_monitor.set_retry_policy(config.max_retries, config.max_retry_delay_ms);
+    chat_monitor.set_quota_tracker(quota.clone());
+    chat_monitor.set_backlog_policy(config.backlog_max_age_secs, config.skip_initial_page);
+
+    let recorder = args.record.as_deref().map(Recorder::create).transpose()?;
+    if let Some(recorder) = &recorder {
+        chat_monitor.set_recorder(recorder.clone());
+    }
 
     // Main processing loop
     tracing::info!("Monitoring chat for video ID: {}", video_id);
     tracing::info!("Press Ctrl+C to exit");
 
-    while let Some(message) = chat_monitor.next_message().await? {
-        println!(
-            "[{}] {}: {}",
-            message.timestamp, message.author, message.text
-        );
+    // Flush every line so the output can be piped into other tools live
+    let mut stdout = std::io::stdout().lock();
+    if let Some(header) = header(args.format, &args.fields) {
+        writeln!(stdout, "{}", header)?;
+        stdout.flush()?;
+    }
+
+    while let Some(mut message) = chat_monitor.next_message().await? {
+        message.source = args.label.clone();
+        if let Some(recorder) = &recorder {
+            recorder.record_message(&message);
+        }
+
+        if args.filter {
+            if let Err(reason) = config
+                .filters_for(&message.source)
+                .try_for_each(|filter| filter.check(&message))
+            {
+                tracing::info!("Filtered message from {}: {}", message.author, reason);
+                continue;
+            }
+        }
+
+        writeln!(
+            stdout,
+            "{}",
+            format_message(args.format, &args.fields, &message)
+        )?;
+        stdout.flush()?;
     }
 
+    quota.save();
     Ok(())
 }
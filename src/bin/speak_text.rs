@@ -4,7 +4,7 @@ use anyhow::Result;
 use clap::Parser;
 
 use config::TtsEngine;
-use youtube_live_tts::{config, tts};
+use youtube_live_tts::{config, markup::Markup, tts};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about = "Simple TTS text speaker")]
@@ -17,9 +17,9 @@ struct Args {
     #[clap(short, long)]
     voice: Option<String>,
 
-    /// TTS engine to use (windows or openai)
-    #[clap(long)]
-    tts_engine: Option<String>,
+    /// TTS engine to use
+    #[clap(long, value_enum)]
+    tts_engine: Option<TtsEngine>,
 
     /// OpenAI voice to use (when using OpenAI TTS)
     #[clap(long)]
@@ -29,6 +29,18 @@ struct Args {
     #[clap(long)]
     openai_model: Option<String>,
 
+    /// Speech rate, 1.0 is normal
+    #[clap(long)]
+    rate: Option<f32>,
+
+    /// Speech pitch, 1.0 is normal
+    #[clap(long)]
+    pitch: Option<f32>,
+
+    /// Speech volume, 1.0 is normal
+    #[clap(long)]
+    volume: Option<f32>,
+
     /// Path to config file (optional)
     #[clap(short, long)]
     config: Option<String>,
@@ -51,17 +63,8 @@ async fn main() -> Result<()> {
     let mut config = config::load_config(args.config.as_deref()).unwrap_or_default();
 
     // Override config with command line arguments if provided
-    if let Some(engine) = &args.tts_engine {
-        match engine.to_lowercase().as_str() {
-            "windows" => config.tts_engine = TtsEngine::Windows,
-            "openai" => config.tts_engine = TtsEngine::OpenAI,
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "Invalid TTS engine: {}. Supported engines: windows, openai",
-                    engine
-                ));
-            }
-        }
+    if let Some(engine) = args.tts_engine {
+        config.tts_engine = engine;
     }
 
     if let Some(voice) = &args.voice {
@@ -76,6 +79,11 @@ async fn main() -> Result<()> {
         config.openai_model = model.clone();
     }
 
+    config.rate = args.rate.unwrap_or(config.rate);
+    config.pitch = args.pitch.unwrap_or(config.pitch);
+    config.volume = args.volume.unwrap_or(config.volume);
+    let prosody = tts::Prosody::from_config(&config);
+
     // Initialize TTS engine
     tracing::info!("Initializing TTS engine: {:?}", config.tts_engine);
     let tts_engine = tts::create_tts_engine(&config)?;
@@ -104,7 +112,7 @@ async fn main() -> Result<()> {
 
             // Speak the line
             tracing::info!("Speaking: {}", line);
-            tts_engine.speak(line)?;
+            tts_engine.speak(&Markup::plain(line), prosody)?;
 
             let wait_time = (line.len() as u64 * 100).max(2000);
             tracing::debug!("Waiting for {}ms for speech to complete", wait_time);
@@ -116,7 +124,7 @@ async fn main() -> Result<()> {
     // Speak the text if provided via command line
     if !text.is_empty() && args.text.is_some() {
         tracing::info!("Speaking: {}", text);
-        tts_engine.speak(&text)?;
+        tts_engine.speak(&Markup::plain(&text), prosody)?;
 
         // Give time for speech to complete based on text length
         let wait_time = (text.len() as u64 * 100).max(5000);
@@ -0,0 +1,192 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+
+// Extension of cached audio files, so unrelated files in the directory are left alone
+const CACHE_EXTENSION: &str = "audio";
+
+/// Synthesized audio on disk, keyed by a hash of everything that affects how
+/// it sounds. Once the files grow past the size limit, the least recently
+/// used ones are deleted.
+#[derive(Debug, Clone)]
+pub struct AudioCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("youtube-live-tts/audio"))
+}
+
+impl AudioCache {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(AppError::IO)?;
+        Ok(Self { dir, max_bytes })
+    }
+
+    /// Cache key for audio made from `parts`, e.g. engine, model, voice,
+    /// options and text
+    pub fn key(parts: &[&str]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part.as_bytes());
+            // Separate parts so ["ab", "c"] and ["a", "bc"] differ
+            hasher.update([0]);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, CACHE_EXTENSION))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.path(key);
+        let data = fs::read(&path).ok()?;
+
+        // The modification time records when an entry was last used
+        if let Err(e) = File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(SystemTime::now()))
+        {
+            tracing::debug!("Failed to touch cached audio {:?}: {}", path, e);
+        }
+
+        tracing::debug!("Audio cache hit: {}", key);
+        Some(data)
+    }
+
+    pub fn put(&self, key: &str, data: &[u8]) {
+        if data.len() as u64 > self.max_bytes {
+            return;
+        }
+
+        let path = self.path(key);
+        // Write under a unique temporary name so a crash never leaves a
+        // truncated entry, and a prefetch and a speak writing the same entry
+        // at once don't mix their data
+        let result = tempfile::NamedTempFile::new_in(&self.dir)
+            .and_then(|mut temp| temp.write_all(data).map(|()| temp))
+            .map_err(anyhow::Error::from)
+            .and_then(|temp| Ok(temp.persist(&path)?))
+            .and_then(|_| self.evict());
+        if let Err(e) = result {
+            tracing::warn!("Failed to cache audio in {:?}: {}", self.dir, e);
+        }
+    }
+
+    // Delete the least recently used entries until the cache fits its limit
+    fn evict(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != CACHE_EXTENSION) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            entries.push((metadata.modified()?, metadata.len(), path));
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(&path)?;
+            total -= len;
+            tracing::debug!("Evicted cached audio {:?}", path);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn age(cache: &AudioCache, key: &str, secs: u64) {
+        File::options()
+            .write(true)
+            .open(cache.path(key))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn keys_depend_on_every_part() {
+        let key = AudioCache::key(&["openai", "tts-1", "alloy", "草"]);
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, AudioCache::key(&["openai", "tts-1", "alloy", "草"]));
+        assert_ne!(key, AudioCache::key(&["openai", "tts-1", "nova", "草"]));
+        assert_ne!(AudioCache::key(&["ab", "c"]), AudioCache::key(&["a", "bc"]));
+    }
+
+    #[test]
+    fn concurrent_writes_of_one_entry_dont_mix() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AudioCache::new(dir.path(), 10_000_000).unwrap();
+
+        std::thread::scope(|scope| {
+            for byte in 1..=4u8 {
+                let cache = &cache;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        cache.put("same", &[byte; 100_000]);
+                    }
+                });
+            }
+        });
+
+        let data = cache.get("same").unwrap();
+        assert_eq!(data.len(), 100_000);
+        assert!(data.iter().all(|b| *b == data[0]));
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AudioCache::new(dir.path(), 25).unwrap();
+
+        cache.put("a", &[1; 10]);
+        cache.put("b", &[2; 10]);
+        age(&cache, "a", 30);
+        age(&cache, "b", 20);
+
+        // Using "a" makes "b" the least recently used
+        assert_eq!(cache.get("a"), Some(vec![1; 10]));
+        cache.put("c", &[3; 10]);
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+
+        // Entries larger than the whole cache are not stored
+        cache.put("big", &[4; 30]);
+        assert!(cache.get("big").is_none());
+    }
+}
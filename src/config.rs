@@ -1,29 +1,76 @@
-use std::{fs, path::Path};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+use crate::cache::{self, AudioCache};
 use crate::error::AppError;
+use crate::filter::Filter;
+use crate::history::{self, History};
+use crate::quota::{self, QuotaTracker};
+use crate::validation;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, clap::ValueEnum)]
 #[serde(rename_all = "lowercase")]
 pub enum TtsEngine {
+    #[default]
     Windows,
+    #[value(name = "openai")]
     OpenAI,
 }
 
-impl Default for TtsEngine {
-    fn default() -> Self {
-        TtsEngine::Windows
+impl TtsEngine {
+    /// Name used in config files and on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            TtsEngine::Windows => "windows",
+            TtsEngine::OpenAI => "openai",
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Prefix of environment variables that override config settings. Nested
+// settings are separated by a double underscore, e.g. YTTTS_FILTER__MAX_LENGTH.
+const ENV_PREFIX: &str = "YTTTS_";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    // Only needed to read YouTube chats
+    #[serde(default)]
     pub api_key: String,
     #[serde(default = "default_poll_interval")]
     pub poll_interval_ms: u64,
 
+    // Chat history returned when first connecting to a chat
+    #[serde(default = "default_backlog_max_age")]
+    pub backlog_max_age_secs: u64,
+    #[serde(default)]
+    pub skip_initial_page: bool,
+
+    // How often to check whether a stream has gone live in --wait mode
+    #[serde(default = "default_wait_poll_interval")]
+    pub wait_poll_interval_secs: u64,
+
+    // Retry policy for failed YouTube API requests
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_max_retry_delay")]
+    pub max_retry_delay_ms: u64,
+
+    // YouTube API quota budgeting
+    #[serde(default = "default_quota_budget")]
+    pub quota_budget: u64,
+    #[serde(default = "default_quota_warn_percent")]
+    pub quota_warn_percent: u64,
+    #[serde(default = "default_expected_stream_hours")]
+    pub expected_stream_hours: f64,
+    pub quota_usage_file: Option<String>,
+
     // For backward compatibility
     #[serde(default = "default_voice")]
     pub voice_name: String,
@@ -32,6 +79,13 @@ pub struct Config {
     #[serde(default)]
     pub tts_engine: TtsEngine,
 
+    // Engines tried in order when tts_engine fails, and how long to wait for an
+    // engine's API to respond
+    #[serde(default)]
+    pub fallback_engines: Vec<TtsEngine>,
+    #[serde(default = "default_tts_timeout")]
+    pub tts_timeout_secs: u64,
+
     // Windows TTS config
     #[serde(default = "default_voice")]
     pub windows_voice: String,
@@ -42,12 +96,167 @@ pub struct Config {
     pub openai_model: String,
     #[serde(default = "default_openai_voice")]
     pub openai_voice: String,
+
+    // Speech rate, pitch and volume; 1.0 is the engine's normal setting
+    #[serde(default = "default_prosody")]
+    pub rate: f32,
+    #[serde(default = "default_prosody")]
+    pub pitch: f32,
+    #[serde(default = "default_prosody")]
+    pub volume: f32,
+
+    // How author names are read, relative to the rest of the message, by
+    // engines that accept SSML
+    #[serde(default = "default_prosody")]
+    pub author_rate: f32,
+    #[serde(default = "default_prosody")]
+    pub author_pitch: f32,
+
+    // Read faster as the queue grows, up to max_rate
+    #[serde(default)]
+    pub adaptive_rate: bool,
+    #[serde(default = "default_max_rate")]
+    pub max_rate: f32,
+
+    // OpenAI request limits, retries on rate limiting and server errors, and
+    // spend tracking. Prices are USD per million characters by model; no more
+    // audio is synthesized once the estimated spend reaches the budget.
+    #[serde(default = "default_openai_requests_per_minute")]
+    pub openai_requests_per_minute: usize,
+    #[serde(default = "default_openai_max_retries")]
+    pub openai_max_retries: u32,
+    #[serde(default = "default_openai_pricing")]
+    pub openai_pricing: HashMap<String, f64>,
+    pub openai_budget_usd: Option<f64>,
+
+    // On-disk cache of synthesized OpenAI audio; 0 disables it
+    #[serde(default = "default_audio_cache_max_mb")]
+    pub audio_cache_max_mb: u64,
+    pub audio_cache_dir: Option<String>,
+
+    // Queued messages synthesized ahead of playback, and how many requests for
+    // them may run at once
+    #[serde(default = "default_prefetch_count")]
+    pub prefetch_count: usize,
+    #[serde(default = "default_prefetch_concurrency")]
+    pub prefetch_concurrency: usize,
+
+    // How chat messages are read. {author}, {text}, {source} and {amount} are replaced
+    #[serde(default = "default_message_template")]
+    pub message_template: String,
+
+    // Longest speech queue before the oldest chat messages are dropped; 0 for no limit
+    #[serde(default = "default_max_queue_length")]
+    pub max_queue_length: usize,
+
+    // Log of what was read aloud, filtered or dropped
+    #[serde(default = "default_save_history")]
+    pub save_history: bool,
+    pub history_file: Option<String>,
+
+    // Apply changes to the config file while the bot is running
+    #[serde(default = "default_watch_config")]
+    pub watch_config: bool,
+
+    // Moderation rules applied to every source
+    #[serde(default)]
+    pub filter: Filter,
+
+    // Per-source settings, keyed by source label
+    #[serde(default)]
+    pub sources: HashMap<String, SourceConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceConfig {
+    // Overrides the global message_template for this source
+    pub message_template: Option<String>,
+
+    // Moderation rules applied on top of the global filter
+    #[serde(flatten)]
+    pub filter: Filter,
 }
 
 fn default_poll_interval() -> u64 {
     3000 // 3 seconds
 }
 
+fn default_backlog_max_age() -> u64 {
+    30
+}
+
+fn default_wait_poll_interval() -> u64 {
+    60
+}
+
+fn default_max_retries() -> u32 {
+    10
+}
+
+fn default_max_retry_delay() -> u64 {
+    60_000 // 1 minute
+}
+
+fn default_quota_budget() -> u64 {
+    10_000 // Default daily quota of a Google Cloud project
+}
+
+fn default_quota_warn_percent() -> u64 {
+    80
+}
+
+fn default_expected_stream_hours() -> f64 {
+    4.0
+}
+
+fn default_openai_requests_per_minute() -> usize {
+    50
+}
+
+fn default_openai_max_retries() -> u32 {
+    3
+}
+
+fn default_openai_pricing() -> HashMap<String, f64> {
+    HashMap::from([("tts-1".to_string(), 15.0), ("tts-1-hd".to_string(), 30.0)])
+}
+
+fn default_tts_timeout() -> u64 {
+    10
+}
+
+fn default_prosody() -> f32 {
+    1.0
+}
+
+fn default_max_rate() -> f32 {
+    2.0
+}
+
+fn default_audio_cache_max_mb() -> u64 {
+    100
+}
+
+fn default_prefetch_count() -> usize {
+    2
+}
+
+fn default_prefetch_concurrency() -> usize {
+    2
+}
+
+fn default_max_queue_length() -> usize {
+    100
+}
+
+fn default_save_history() -> bool {
+    true
+}
+
+fn default_watch_config() -> bool {
+    true
+}
+
 fn default_voice() -> String {
     "Microsoft David".to_string()
 }
@@ -60,49 +269,323 @@ fn default_openai_voice() -> String {
     "alloy".to_string()
 }
 
+fn default_message_template() -> String {
+    "{author}さん: {text}".to_string()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             api_key: String::new(),
             poll_interval_ms: default_poll_interval(),
+            backlog_max_age_secs: default_backlog_max_age(),
+            skip_initial_page: false,
+            wait_poll_interval_secs: default_wait_poll_interval(),
+            max_retries: default_max_retries(),
+            max_retry_delay_ms: default_max_retry_delay(),
+            quota_budget: default_quota_budget(),
+            quota_warn_percent: default_quota_warn_percent(),
+            expected_stream_hours: default_expected_stream_hours(),
+            quota_usage_file: None,
             voice_name: default_voice(),
             tts_engine: TtsEngine::default(),
+            fallback_engines: Vec::new(),
+            tts_timeout_secs: default_tts_timeout(),
             windows_voice: default_voice(),
             openai_api_key: None,
             openai_model: default_openai_model(),
             openai_voice: default_openai_voice(),
+            openai_requests_per_minute: default_openai_requests_per_minute(),
+            openai_max_retries: default_openai_max_retries(),
+            openai_pricing: default_openai_pricing(),
+            openai_budget_usd: None,
+            rate: default_prosody(),
+            pitch: default_prosody(),
+            volume: default_prosody(),
+            author_rate: default_prosody(),
+            author_pitch: default_prosody(),
+            adaptive_rate: false,
+            max_rate: default_max_rate(),
+            audio_cache_max_mb: default_audio_cache_max_mb(),
+            audio_cache_dir: None,
+            prefetch_count: default_prefetch_count(),
+            prefetch_concurrency: default_prefetch_concurrency(),
+            message_template: default_message_template(),
+            max_queue_length: default_max_queue_length(),
+            save_history: default_save_history(),
+            history_file: None,
+            watch_config: default_watch_config(),
+            filter: Filter::default(),
+            sources: HashMap::new(),
         }
     }
 }
 
+/// Settings layered over the config file: `YTTTS_*` environment variables,
+/// then `--set key=value` arguments. Later entries win.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    entries: Vec<(String, String)>,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides from the `YTTTS_*` variables of this process
+    pub fn from_env() -> Self {
+        Self::from_vars(std::env::vars())
+    }
+
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut overrides = Self::new();
+        for (name, value) in vars {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                overrides.set(key.to_lowercase().replace("__", "."), value);
+            }
+        }
+        overrides
+    }
+
+    /// Set a dotted key such as `filter.max_length`
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((key.into(), value.into()));
+    }
+
+    /// Add a `key=value` argument
+    pub fn parse_arg(&mut self, arg: &str) -> Result<()> {
+        match arg.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                self.set(key.trim(), value);
+                Ok(())
+            }
+            _ => Err(AppError::Config(format!("Expected key=value, got `{}`", arg)).into()),
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.iter().any(|(k, _)| k == key)
+    }
+
+    /// Write the overrides into a parsed config file
+    pub fn apply(&self, table: &mut toml::Table) -> Result<()> {
+        for (key, value) in &self.entries {
+            let (parents, name) = match key.rsplit_once('.') {
+                Some((parents, name)) => (parents.split('.').collect(), name),
+                None => (Vec::new(), key.as_str()),
+            };
+
+            let mut target = &mut *table;
+            for parent in parents {
+                target = match target
+                    .entry(parent)
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                {
+                    toml::Value::Table(t) => t,
+                    _ => {
+                        return Err(AppError::Config(format!(
+                            "Can't override `{}`: `{}` is not a table",
+                            key, parent
+                        ))
+                        .into())
+                    }
+                };
+            }
+            target.insert(name.to_string(), override_value(key, value));
+        }
+        Ok(())
+    }
+}
+
+// Settings holding a single string, including optional ones, whose override
+// values are never read as TOML
+const TEXT_SETTINGS: [&str; 11] = [
+    "api_key",
+    "quota_usage_file",
+    "voice_name",
+    "tts_engine",
+    "windows_voice",
+    "openai_api_key",
+    "openai_model",
+    "openai_voice",
+    "audio_cache_dir",
+    "message_template",
+    "history_file",
+];
+
+// Values are read as TOML, so numbers, booleans and arrays keep their type.
+// Anything that isn't valid TOML, or is meant for a text setting, is text.
+fn override_value(key: &str, value: &str) -> toml::Value {
+    // Per-source settings share their names with the top-level ones
+    let name = match key.strip_prefix("sources.") {
+        Some(rest) => rest.split_once('.').map_or(rest, |(_, name)| name),
+        None => key,
+    };
+    if TEXT_SETTINGS.contains(&name) {
+        return toml::Value::String(value.to_string());
+    }
+
+    format!("value = {}", value)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+/// Load the config file with `YTTTS_*` environment variable overrides
 pub fn load_config(config_path: Option<&str>) -> Result<Config> {
+    load_config_with(config_path, &Overrides::from_env())
+}
+
+pub fn load_config_with(config_path: Option<&str>, overrides: &Overrides) -> Result<Config> {
+    checked_config(&load_table(config_path, overrides)?)
+}
+
+/// Like `load_config_with`, but without a config file the defaults and
+/// overrides are used, for commands that don't read YouTube chats
+pub fn load_config_or_defaults(config_path: Option<&str>, overrides: &Overrides) -> Result<Config> {
+    checked_config(&table_with_overrides(config_path, overrides, true)?)
+}
+
+fn checked_config(table: &toml::Table) -> Result<Config> {
+    let config = config_from_table(table)?;
+    validation::validate(table, &config)?;
+    Ok(config)
+}
+
+/// The config file with overrides applied, before it is deserialized. Without
+/// a config file, overrides can provide every setting if they include the
+/// API key.
+pub fn load_table(config_path: Option<&str>, overrides: &Overrides) -> Result<toml::Table> {
+    table_with_overrides(config_path, overrides, overrides.contains("api_key"))
+}
+
+fn table_with_overrides(
+    config_path: Option<&str>,
+    overrides: &Overrides,
+    without_file: bool,
+) -> Result<toml::Table> {
+    let mut table = match find_config_file(config_path) {
+        Ok(path) => read_table(path)?,
+        Err(_) if config_path.is_none() && without_file => toml::Table::new(),
+        Err(e) => return Err(e),
+    };
+    overrides.apply(&mut table)?;
+    Ok(table)
+}
+
+pub fn read_table(path: impl AsRef<Path>) -> Result<toml::Table> {
+    Ok(fs::read_to_string(path)?.parse::<toml::Table>()?)
+}
+
+/// The config file to use: the given path, or the first default location that exists
+pub fn find_config_file(config_path: Option<&str>) -> Result<PathBuf> {
     // If config path is provided, load from there
     if let Some(path) = config_path {
-        return parse_config_file(path);
+        return Ok(PathBuf::from(path));
     }
 
     // Otherwise check default locations
     if let Some(config_dir) = dirs::config_dir() {
         let default_path = config_dir.join("youtube-live-tts/config.toml");
         if default_path.exists() {
-            return parse_config_file(default_path);
+            return Ok(default_path);
         }
     }
 
     // Fallback to current directory
     let local_config = Path::new("config.toml");
     if local_config.exists() {
-        return parse_config_file(local_config);
+        return Ok(local_config.to_path_buf());
     }
 
     // If no config found, return error
-    Err(AppError::Config("No configuration file found. Please provide API key".to_string()).into())
+    Err(AppError::Config(
+        "No configuration file found. Please provide a config file or set YTTTS_API_KEY"
+            .to_string(),
+    )
+    .into())
 }
 
-fn parse_config_file<P: AsRef<Path>>(path: P) -> Result<Config> {
-    let content = fs::read_to_string(path)?.parse::<toml::Table>()?;
+impl Config {
+    pub fn message_template_for(&self, source: &str) -> &str {
+        self.sources
+            .get(source)
+            .and_then(|s| s.message_template.as_deref())
+            .unwrap_or(&self.message_template)
+    }
+
+    /// Global filter followed by the filter of the source, if any
+    pub fn filters_for(&self, source: &str) -> impl Iterator<Item = &Filter> {
+        std::iter::once(&self.filter).chain(self.sources.get(source).map(|s| &s.filter))
+    }
+
+    /// Top-level settings whose values differ in `other`
+    pub fn changed_keys(&self, other: &Config) -> Vec<String> {
+        let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+            (serde_json::to_value(self), serde_json::to_value(other))
+        else {
+            return Vec::new();
+        };
+        old.iter()
+            .filter(|(key, value)| new.get(*key) != Some(*value))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    pub fn history_path(&self) -> Option<PathBuf> {
+        match &self.history_file {
+            Some(path) => Some(PathBuf::from(path)),
+            None => history::default_history_path(),
+        }
+    }
+
+    /// Open the spoken-history log, unless it is disabled
+    pub fn history(&self) -> Result<Option<History>> {
+        if !self.save_history {
+            return Ok(None);
+        }
+        self.history_path().map(History::open).transpose()
+    }
+
+    /// The synthesized-audio cache, unless it is disabled or can't be created
+    pub fn audio_cache(&self) -> Option<AudioCache> {
+        if self.audio_cache_max_mb == 0 {
+            return None;
+        }
 
-    let mut config: Config = toml::from_str(&content.to_string())?;
+        let dir = match &self.audio_cache_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => cache::default_cache_dir()?,
+        };
+        match AudioCache::new(&dir, self.audio_cache_max_mb * 1024 * 1024) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                tracing::warn!("Audio cache disabled, failed to create {:?}: {}", dir, e);
+                None
+            }
+        }
+    }
+
+    pub fn quota_tracker(&self) -> QuotaTracker {
+        let path = match &self.quota_usage_file {
+            Some(path) => Some(PathBuf::from(path)),
+            None => quota::default_usage_path(),
+        };
+
+        QuotaTracker::new(
+            path,
+            self.quota_budget,
+            self.quota_warn_percent,
+            Duration::from_secs_f64(self.expected_stream_hours.max(0.0) * 3600.0),
+        )
+    }
+}
+
+/// Deserialize a parsed config file without validating it
+pub fn config_from_table(table: &toml::Table) -> Result<Config> {
+    let mut config: Config = toml::Value::Table(table.clone()).try_into()?;
 
     // For backward compatibility: if voice_name is set but windows_voice isn't,
     // copy the value to windows_voice
@@ -112,3 +595,50 @@ fn parse_config_file<P: AsRef<Path>>(path: P) -> Result<Config> {
 
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_layer_over_the_file() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            api_key = "from-file"
+            poll_interval_ms = 5000
+
+            [filter]
+            max_length = 50
+            "#,
+        )
+        .unwrap();
+
+        let mut overrides = Overrides::from_vars([
+            ("YTTTS_API_KEY".to_string(), "12345".to_string()),
+            ("YTTTS_OPENAI_API_KEY".to_string(), "67890".to_string()),
+            ("YTTTS_POLL_INTERVAL_MS".to_string(), "1000".to_string()),
+            ("YTTTS_FILTER__MAX_LENGTH".to_string(), "80".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ]);
+        overrides.parse_arg("poll_interval_ms=2000").unwrap();
+        overrides.parse_arg("history_file=true").unwrap();
+        overrides
+            .parse_arg("fallback_engines=[\"openai\"]")
+            .unwrap();
+        overrides
+            .parse_arg("sources.main.message_template={author}: {text}")
+            .unwrap();
+        assert!(overrides.parse_arg("no_value").is_err());
+        overrides.apply(&mut table).unwrap();
+
+        let config = config_from_table(&table).unwrap();
+        // Numbers stay text for text settings
+        assert_eq!(config.api_key, "12345");
+        assert_eq!(config.openai_api_key.as_deref(), Some("67890"));
+        assert_eq!(config.history_file.as_deref(), Some("true"));
+        assert_eq!(config.poll_interval_ms, 2000);
+        assert_eq!(config.filter.max_length, Some(80));
+        assert_eq!(config.fallback_engines, [TtsEngine::OpenAI]);
+        assert_eq!(config.message_template_for("main"), "{author}: {text}");
+    }
+}
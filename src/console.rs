@@ -0,0 +1,155 @@
+use anyhow::Result;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    sync::mpsc,
+};
+
+use crate::error::AppError;
+
+/// Operator commands accepted on stdin while the bot is running
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Skip,
+    Pause,
+    Resume,
+    Say(String),
+    Mute(String),
+    Unmute(String),
+    Voice(String),
+    Queue,
+    Stats,
+    Help,
+}
+
+pub const HELP: &str = "\
+Commands:
+  skip            Stop the current message
+  pause           Stop reading, holding OpenAI audio that is playing
+  resume          Resume reading
+  say <text>      Read text next, ahead of the queue
+  mute <author>   Ignore messages from an author
+  unmute <author> Stop ignoring an author
+  voice <name>    Switch the voice of the current TTS engine
+  queue           Show queued messages
+  stats           Show session statistics
+  help            Show this help";
+
+pub fn parse_command(line: &str) -> Result<Option<Command>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let (name, arg) = match line.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (line, ""),
+    };
+
+    let require_arg = |usage: &str| -> Result<String> {
+        if arg.is_empty() {
+            Err(AppError::Command(format!("Usage: {}", usage)).into())
+        } else {
+            Ok(arg.to_string())
+        }
+    };
+
+    let command = match name.to_lowercase().as_str() {
+        "skip" => Command::Skip,
+        "pause" => Command::Pause,
+        "resume" => Command::Resume,
+        "say" => Command::Say(require_arg("say <text>")?),
+        "mute" => Command::Mute(require_arg("mute <author>")?),
+        "unmute" => Command::Unmute(require_arg("unmute <author>")?),
+        "voice" => Command::Voice(require_arg("voice <name>")?),
+        "queue" => Command::Queue,
+        "stats" => Command::Stats,
+        "help" | "?" => Command::Help,
+        _ => {
+            return Err(AppError::Command(format!(
+                "Unknown command: {}. Type 'help' for a list of commands",
+                name
+            ))
+            .into())
+        }
+    };
+
+    Ok(Some(command))
+}
+
+// Read lines from stdin in the background and forward them to the caller
+pub fn spawn_stdin_reader() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {
+                    tracing::debug!("stdin closed, console commands disabled");
+                    break;
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to read from stdin: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        let cases = [
+            ("skip", Some(Command::Skip)),
+            ("  PAUSE  ", Some(Command::Pause)),
+            ("resume", Some(Command::Resume)),
+            (
+                "say  hello there ",
+                Some(Command::Say("hello there".to_string())),
+            ),
+            ("mute Spammer", Some(Command::Mute("Spammer".to_string()))),
+            (
+                "unmute Spammer",
+                Some(Command::Unmute("Spammer".to_string())),
+            ),
+            ("voice\tnova", Some(Command::Voice("nova".to_string()))),
+            ("queue", Some(Command::Queue)),
+            ("stats", Some(Command::Stats)),
+            ("help", Some(Command::Help)),
+            ("?", Some(Command::Help)),
+            ("", None),
+            ("   ", None),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(parse_command(line).unwrap(), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let cases = [
+            ("say", "Usage: say <text>"),
+            ("mute   ", "Usage: mute <author>"),
+            ("unmute", "Usage: unmute <author>"),
+            ("voice", "Usage: voice <name>"),
+            ("jump now", "Unknown command: jump"),
+        ];
+
+        for (line, expected) in cases {
+            let error = parse_command(line).unwrap_err().to_string();
+            assert!(error.contains(expected), "{:?}: {}", line, error);
+        }
+    }
+}
@@ -8,6 +8,46 @@ pub enum AppError {
     #[error("YouTube API error: {0}")]
     YouTube(String),
 
+    #[error(
+        "YouTube API quota exceeded. The daily quota resets at midnight Pacific Time; \
+         raise poll_interval_ms or request a higher quota in the Google Cloud Console"
+    )]
+    QuotaExceeded,
+
+    #[error("YouTube API rate limit exceeded")]
+    RateLimitExceeded,
+
+    #[error("YouTube live chat has ended")]
+    LiveChatEnded,
+
+    #[error("Live chat is disabled for this stream")]
+    LiveChatDisabled,
+
+    #[error("Live chat not found. Check that the stream is live and the video ID is correct")]
+    LiveChatNotFound,
+
+    #[error("YouTube API key is invalid or expired. Check api_key in your config file")]
+    InvalidApiKey,
+
+    #[error(
+        "YouTube Data API v3 is not enabled for this API key. \
+         Enable it for your project in the Google Cloud Console"
+    )]
+    ApiNotEnabled,
+
+    #[error("YouTube API access forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("YouTube API error (HTTP {status}, {reason}): {message}")]
+    YouTubeApi {
+        status: u16,
+        reason: String,
+        message: String,
+    },
+
+    #[error("YouTube API server error (HTTP {0})")]
+    ServerError(u16),
+
     #[error("TTS engine error: {0}")]
     Tts(String),
 
@@ -20,6 +60,66 @@ pub enum AppError {
     #[error("JSON parsing error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("Command error: {0}")]
+    Command(String),
+
     #[error("Windows API error: {0}")]
     Windows(String),
 }
+
+impl AppError {
+    /// Whether the failed operation may succeed if it is simply tried again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AppError::RateLimitExceeded | AppError::ServerError(_) | AppError::Json(_) => true,
+            AppError::Http(e) => {
+                e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() || e.is_decode()
+            }
+            _ => false,
+        }
+    }
+}
+
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<AppError>()
+        .is_some_and(AppError::is_retryable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let json_error = serde_json::from_str::<u32>("x").unwrap_err();
+        let cases = [
+            (AppError::RateLimitExceeded, true),
+            (AppError::ServerError(503), true),
+            (AppError::Json(json_error), true),
+            (AppError::QuotaExceeded, false),
+            (AppError::InvalidApiKey, false),
+            (AppError::ApiNotEnabled, false),
+            (AppError::LiveChatEnded, false),
+            (AppError::LiveChatNotFound, false),
+            (AppError::Forbidden("rateLimitExceeded".to_string()), false),
+            (AppError::Config("missing api_key".to_string()), false),
+        ];
+
+        for (error, retryable) in cases {
+            let description = error.to_string();
+            assert_eq!(error.is_retryable(), retryable, "{}", description);
+            assert_eq!(
+                is_retryable(&anyhow::Error::from(error)),
+                retryable,
+                "{}",
+                description
+            );
+        }
+
+        // Errors that aren't AppErrors are never retried
+        assert!(!is_retryable(&anyhow::anyhow!("something else")));
+        // Context added on the way up doesn't hide the cause
+        let wrapped = anyhow::Error::from(AppError::ServerError(500)).context("polling chat");
+        assert!(is_retryable(&wrapped));
+    }
+}
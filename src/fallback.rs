@@ -0,0 +1,375 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::error::AppError;
+use crate::markup::Markup;
+use crate::tts::{Prosody, TextToSpeech};
+
+// Consecutive failures after which an engine is skipped for a while
+const FAILURE_THRESHOLD: u32 = 3;
+
+// How long a failing engine is skipped before it is tried again
+const OPEN_DURATION: Duration = Duration::from_secs(60);
+
+// Tracks an engine's recent failures, so a broken engine is skipped instead of
+// delaying every message while it fails
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    // Once the wait is over the engine is tried again, and a single failure
+    // skips it for another OPEN_DURATION
+    fn allows(&self, now: Instant) -> bool {
+        self.open_until.is_none_or(|until| now >= until)
+    }
+
+    fn record_success(&mut self) {
+        self.failures = 0;
+        self.open_until = None;
+    }
+
+    fn record_failure(&mut self, now: Instant) {
+        self.failures += 1;
+        if self.failures >= FAILURE_THRESHOLD {
+            self.open_until = Some(now + OPEN_DURATION);
+        }
+    }
+
+    fn describe(&self, now: Instant) -> String {
+        match self.open_until {
+            Some(until) if now < until => {
+                format!("open, retrying in {}s", (until - now).as_secs())
+            }
+            Some(_) => "half-open".to_string(),
+            None if self.failures > 0 => format!("closed, {} recent failures", self.failures),
+            None => "closed".to_string(),
+        }
+    }
+}
+
+struct Link {
+    name: String,
+    engine: Box<dyn TextToSpeech>,
+}
+
+// The message being spoken and the engine speaking it
+struct Attempt {
+    speech: Markup,
+    prosody: Prosody,
+    engine: usize,
+}
+
+#[derive(Default)]
+struct State {
+    breakers: Vec<CircuitBreaker>,
+    attempt: Option<Attempt>,
+    error: Option<String>,
+}
+
+/// Speaks with the first healthy engine of a chain, moving a message on to the
+/// next engine when one fails or times out
+pub struct FallbackEngine {
+    links: Vec<Link>,
+    state: Mutex<State>,
+}
+
+impl FallbackEngine {
+    pub fn new() -> Self {
+        Self {
+            links: Vec::new(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Add an engine to the end of the chain
+    pub fn push(&mut self, name: impl Into<String>, engine: Box<dyn TextToSpeech>) {
+        self.links.push(Link {
+            name: name.into(),
+            engine,
+        });
+        self.state
+            .get_mut()
+            .expect("fallback lock poisoned")
+            .breakers
+            .push(CircuitBreaker::default());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    // Start speaking with the first engine from `first` on that is healthy and
+    // accepts the message. Fails with the last engine's error, or `None` if
+    // no engine could be tried.
+    fn start(
+        &self,
+        state: &mut State,
+        speech: Markup,
+        prosody: Prosody,
+        first: usize,
+    ) -> Result<(), Option<anyhow::Error>> {
+        let now = Instant::now();
+        let mut last_error = None;
+
+        for (index, link) in self.links.iter().enumerate().skip(first) {
+            if !state.breakers[index].allows(now) {
+                continue;
+            }
+            match link.engine.speak(&speech, prosody) {
+                Ok(()) => {
+                    state.attempt = Some(Attempt {
+                        speech,
+                        prosody,
+                        engine: index,
+                    });
+                    return Ok(());
+                }
+                Err(e) => {
+                    tracing::warn!("{} TTS failed: {}", link.name, e);
+                    state.breakers[index].record_failure(now);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+impl Default for FallbackEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextToSpeech for FallbackEngine {
+    fn speak(&self, speech: &Markup, prosody: Prosody) -> Result<()> {
+        let mut state = self.state.lock().expect("fallback lock poisoned");
+        state.error = None;
+        self.start(&mut state, speech.clone(), prosody, 0)
+            .map_err(|e| {
+                e.unwrap_or_else(|| {
+                    AppError::Tts("All TTS engines are unavailable".to_string()).into()
+                })
+            })
+    }
+
+    // Engines that fail in the background are noticed here, once they stop
+    // speaking, and the message is handed to the next engine
+    fn is_speaking(&self) -> bool {
+        let mut state = self.state.lock().expect("fallback lock poisoned");
+        let Some(attempt) = state.attempt.take() else {
+            return false;
+        };
+
+        let link = &self.links[attempt.engine];
+        if link.engine.is_speaking() {
+            state.attempt = Some(attempt);
+            return true;
+        }
+
+        let Some(error) = link.engine.take_error() else {
+            state.breakers[attempt.engine].record_success();
+            return false;
+        };
+
+        tracing::warn!(
+            "{} TTS failed, trying the next engine: {}",
+            link.name,
+            error
+        );
+        state.breakers[attempt.engine].record_failure(Instant::now());
+        let next = attempt.engine + 1;
+        match self.start(&mut state, attempt.speech, attempt.prosody, next) {
+            Ok(()) => true,
+            Err(e) => {
+                state.error = Some(e.map_or(error, |e| e.to_string()));
+                false
+            }
+        }
+    }
+
+    fn stop(&self) {
+        let state = self.state.lock().expect("fallback lock poisoned");
+        if let Some(attempt) = &state.attempt {
+            self.links[attempt.engine].engine.stop();
+        }
+    }
+
+    fn pause(&self) {
+        let state = self.state.lock().expect("fallback lock poisoned");
+        if let Some(attempt) = &state.attempt {
+            self.links[attempt.engine].engine.pause();
+        }
+    }
+
+    fn resume(&self) {
+        let state = self.state.lock().expect("fallback lock poisoned");
+        if let Some(attempt) = &state.attempt {
+            self.links[attempt.engine].engine.resume();
+        }
+    }
+
+    fn current_voice(&self) -> Option<String> {
+        let state = self.state.lock().expect("fallback lock poisoned");
+        let attempt = state.attempt.as_ref()?;
+        self.links[attempt.engine].engine.current_voice()
+    }
+
+    // Only the engine expected to speak next prefetches
+    fn prefetch(&self, upcoming: &[String], prosody: Prosody) {
+        let state = self.state.lock().expect("fallback lock poisoned");
+        let now = Instant::now();
+        let next = self
+            .links
+            .iter()
+            .zip(&state.breakers)
+            .find(|(_, breaker)| breaker.allows(now));
+        if let Some((link, _)) = next {
+            link.engine.prefetch(upcoming, prosody);
+        }
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.state
+            .lock()
+            .expect("fallback lock poisoned")
+            .error
+            .take()
+    }
+
+    fn status(&self) -> Option<String> {
+        let state = self.state.lock().expect("fallback lock poisoned");
+        let now = Instant::now();
+        let status = self
+            .links
+            .iter()
+            .zip(&state.breakers)
+            .map(|(link, breaker)| match link.engine.status() {
+                Some(status) => format!("{} ({}; {})", link.name, breaker.describe(now), status),
+                None => format!("{} ({})", link.name, breaker.describe(now)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    // Fails every message in the background, like an API that is down
+    #[derive(Default)]
+    struct BrokenEngine {
+        attempts: AtomicUsize,
+        failed: Mutex<Option<String>>,
+    }
+
+    impl TextToSpeech for Arc<BrokenEngine> {
+        fn speak(&self, _speech: &Markup, _prosody: Prosody) -> Result<()> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            *self.failed.lock().unwrap() = Some("HTTP 429".to_string());
+            Ok(())
+        }
+
+        fn is_speaking(&self) -> bool {
+            false
+        }
+
+        fn stop(&self) {}
+
+        fn take_error(&self) -> Option<String> {
+            self.failed.lock().unwrap().take()
+        }
+
+        fn current_voice(&self) -> Option<String> {
+            Some("broken voice".to_string())
+        }
+    }
+
+    #[derive(Default)]
+    struct WorkingEngine {
+        spoken: Mutex<Vec<String>>,
+    }
+
+    impl TextToSpeech for Arc<WorkingEngine> {
+        fn speak(&self, speech: &Markup, _prosody: Prosody) -> Result<()> {
+            self.spoken.lock().unwrap().push(speech.to_plain());
+            Ok(())
+        }
+
+        fn is_speaking(&self) -> bool {
+            false
+        }
+
+        fn stop(&self) {}
+
+        fn current_voice(&self) -> Option<String> {
+            Some("working voice".to_string())
+        }
+    }
+
+    #[test]
+    fn fails_over_and_opens_the_circuit() {
+        let broken = Arc::new(BrokenEngine::default());
+        let working = Arc::new(WorkingEngine::default());
+        let mut chain = FallbackEngine::new();
+        chain.push("broken", Box::new(broken.clone()));
+        chain.push("working", Box::new(working.clone()));
+
+        for i in 0..5 {
+            chain
+                .speak(&Markup::plain(&i.to_string()), Prosody::default())
+                .unwrap();
+            while chain.is_speaking() {}
+            assert_eq!(chain.take_error(), None);
+        }
+
+        // Every message was read, and the broken engine was skipped once it
+        // had failed often enough
+        assert_eq!(*working.spoken.lock().unwrap(), ["0", "1", "2", "3", "4"]);
+        assert_eq!(broken.attempts.load(Ordering::SeqCst), 3);
+        let status = chain.status().unwrap();
+        assert!(status.starts_with("broken (open"), "{}", status);
+        assert!(status.ends_with("working (closed)"), "{}", status);
+    }
+
+    #[test]
+    fn reports_failure_when_every_engine_fails() {
+        let mut chain = FallbackEngine::new();
+        chain.push("broken", Box::new(Arc::new(BrokenEngine::default())));
+
+        chain
+            .speak(&Markup::plain("hello"), Prosody::default())
+            .unwrap();
+        assert!(!chain.is_speaking());
+        assert_eq!(chain.take_error().as_deref(), Some("HTTP 429"));
+    }
+
+    #[test]
+    fn reports_the_voice_that_speaks() {
+        let mut chain = FallbackEngine::new();
+        chain.push("broken", Box::new(Arc::new(BrokenEngine::default())));
+        chain.push("working", Box::new(Arc::new(WorkingEngine::default())));
+
+        chain
+            .speak(&Markup::plain("hello"), Prosody::default())
+            .unwrap();
+        assert_eq!(chain.current_voice().as_deref(), Some("broken voice"));
+        // The broken engine's failure hands the message on
+        assert!(chain.is_speaking());
+        assert_eq!(chain.current_voice().as_deref(), Some("working voice"));
+    }
+}
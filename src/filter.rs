@@ -0,0 +1,66 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::youtube::ChatMessage;
+
+/// Moderation rules deciding which chat messages are read aloud
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Filter {
+    // Authors whose messages are never read (case-insensitive)
+    #[serde(default)]
+    pub muted_authors: Vec<String>,
+
+    // Messages containing any of these words are not read (case-insensitive)
+    #[serde(default)]
+    pub blocked_words: Vec<String>,
+
+    // Messages longer than this many characters are not read
+    pub max_length: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterReason {
+    MutedAuthor,
+    BlockedWord(String),
+    TooLong(usize),
+}
+
+impl fmt::Display for FilterReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterReason::MutedAuthor => write!(f, "muted author"),
+            FilterReason::BlockedWord(word) => write!(f, "blocked word '{}'", word),
+            FilterReason::TooLong(len) => write!(f, "too long ({} characters)", len),
+        }
+    }
+}
+
+impl Filter {
+    pub fn check(&self, message: &ChatMessage) -> Result<(), FilterReason> {
+        let author = message.author.to_lowercase();
+        if self
+            .muted_authors
+            .iter()
+            .any(|muted| muted.to_lowercase() == author)
+        {
+            return Err(FilterReason::MutedAuthor);
+        }
+
+        let text = message.text.to_lowercase();
+        if let Some(word) = self
+            .blocked_words
+            .iter()
+            .find(|word| !word.is_empty() && text.contains(&word.to_lowercase()))
+        {
+            return Err(FilterReason::BlockedWord(word.clone()));
+        }
+
+        let len = message.text.chars().count();
+        if self.max_length.is_some_and(|max| len > max) {
+            return Err(FilterReason::TooLong(len));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::source::ChatMessage;
+
+/// What happened to a chat message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "disposition", rename_all = "snake_case")]
+pub enum Disposition {
+    Spoken,
+    /// Cut off by the `skip` command
+    Skipped,
+    Filtered {
+        reason: String,
+    },
+    /// Removed from a full queue to make room for newer messages
+    Dropped,
+    Failed {
+        error: String,
+    },
+}
+
+impl Disposition {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Disposition::Spoken => "spoken",
+            Disposition::Skipped => "skipped",
+            Disposition::Filtered { .. } => "filtered",
+            Disposition::Dropped => "dropped",
+            Disposition::Failed { .. } => "failed",
+        }
+    }
+}
+
+/// One line of the spoken-history log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The chat message, or `None` for text queued with the `say` command
+    pub message: Option<ChatMessage>,
+    /// The text as rendered for speech
+    pub text: String,
+    pub voice: Option<String>,
+    #[serde(flatten)]
+    pub disposition: Disposition,
+    /// When the bot received the message (RFC 3339)
+    pub received_at: String,
+    /// When speech started, for spoken, skipped and failed messages
+    pub started_at: Option<String>,
+    /// Time spent waiting in the queue
+    pub queued_ms: Option<u64>,
+    /// Time spent speaking
+    pub spoken_ms: Option<u64>,
+}
+
+impl HistoryEntry {
+    fn author(&self) -> Option<&str> {
+        self.message.as_ref().map(|m| m.author.as_str())
+    }
+}
+
+pub fn default_history_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("youtube-live-tts/history.jsonl"))
+}
+
+/// Appends what happened to every message to a JSON-lines file
+pub struct History {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl History {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(AppError::IO)?;
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+
+    // History must never stop the bot, so failures are only logged
+    pub fn append(&mut self, entry: &HistoryEntry) {
+        let result = serde_json::to_writer(&mut self.writer, entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(self.writer.write_all(b"\n")?))
+            .and_then(|()| Ok(self.writer.flush()?));
+        if let Err(e) = result {
+            tracing::warn!("Failed to write history to {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Criteria for searching the history. Unset criteria match everything.
+#[derive(Debug, Default)]
+pub struct HistoryQuery {
+    /// Author name, case-insensitive
+    pub author: Option<String>,
+    /// Local date the message was received on
+    pub date: Option<NaiveDate>,
+    /// Word in the original or rendered text, case-insensitive
+    pub keyword: Option<String>,
+    /// Disposition name, e.g. "filtered"
+    pub disposition: Option<String>,
+}
+
+impl HistoryQuery {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        if let Some(author) = &self.author {
+            if entry
+                .author()
+                .is_none_or(|a| a.to_lowercase() != author.to_lowercase())
+            {
+                return false;
+            }
+        }
+
+        if let Some(date) = self.date {
+            let received = DateTime::parse_from_rfc3339(&entry.received_at)
+                .map(|t| t.with_timezone(&Local).date_naive());
+            if received.ok() != Some(date) {
+                return false;
+            }
+        }
+
+        if let Some(keyword) = &self.keyword {
+            let keyword = keyword.to_lowercase();
+            let in_message = entry
+                .message
+                .as_ref()
+                .is_some_and(|m| m.text.to_lowercase().contains(&keyword));
+            if !in_message && !entry.text.to_lowercase().contains(&keyword) {
+                return false;
+            }
+        }
+
+        if let Some(disposition) = &self.disposition {
+            if !entry.disposition.name().eq_ignore_ascii_case(disposition) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Read the entries of a history file that match `query`, oldest first
+pub fn query(path: impl AsRef<Path>, query: &HistoryQuery) -> Result<Vec<HistoryEntry>> {
+    let file = File::open(path.as_ref()).map_err(AppError::IO)?;
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(AppError::IO)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<HistoryEntry>(&line) {
+            Ok(entry) if query.matches(&entry) => entries.push(entry),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Skipping malformed history line: {}", e),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// One-line summary of an entry for the `history` command
+pub fn format_entry(entry: &HistoryEntry) -> String {
+    let mut line = format!(
+        "[{}] {:<8} {}",
+        entry.received_at,
+        entry.disposition.name(),
+        entry.text
+    );
+
+    match &entry.disposition {
+        Disposition::Filtered { reason } => line.push_str(&format!(" ({})", reason)),
+        Disposition::Failed { error } => line.push_str(&format!(" ({})", error)),
+        _ => {}
+    }
+
+    line
+}
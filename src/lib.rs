@@ -1,4 +1,21 @@
+pub mod backoff;
+pub mod cache;
 pub mod config;
+pub mod console;
 pub mod error;
+pub mod fallback;
+pub mod filter;
+pub mod history;
+pub mod markup;
+pub mod playback;
+pub mod quota;
+pub mod recorder;
+pub mod reload;
+pub mod session;
+pub mod source;
+pub mod target;
 pub mod tts;
+pub mod twitch;
+pub mod usage;
+pub mod validation;
 pub mod youtube;
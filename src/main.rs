@@ -1,35 +1,343 @@
-mod config;
-mod error;
-mod tts;
-mod youtube;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use tokio::{sync::mpsc, task::JoinSet};
 
-use config::TtsEngine;
+use config::{Config, Overrides, TtsEngine};
+use youtube_live_tts::{
+    config, console,
+    history::{self, HistoryQuery},
+    quota::QuotaTracker,
+    recorder::Recorder,
+    reload::ConfigWatcher,
+    session::Session,
+    source::{ChatSource, ReplaySource, StdinSource, WebSocketSource},
+    target::Target,
+    tts,
+    twitch::TwitchChat,
+    usage::TtsUsage,
+    validation::{self, Severity},
+    youtube,
+};
+
+// How often the speech queue is checked for the next message
+const SPEECH_TICK_MS: u64 = 100;
+
+// How often the config file is checked for changes
+const CONFIG_POLL_SECS: u64 = 2;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 struct Args {
-    /// YouTube Live video ID
-    #[clap(short, long, group = "target")]
-    video_id: Option<String>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// YouTube Live video ID or URL. Repeat to read several streams; prefix
+    /// with `label=` to name a stream for templates and per-source settings
+    #[clap(short, long)]
+    video_id: Vec<String>,
+
+    /// YouTube channel ID, @handle, username or URL. Can be repeated and
+    /// labeled like --video-id
+    #[clap(short, long)]
+    channel_id: Vec<String>,
+
+    /// Twitch channel name or URL. Can be repeated and labeled like --video-id
+    #[clap(long)]
+    twitch: Vec<String>,
+
+    /// Read JSON-lines chat messages from stdin. Console commands are not
+    /// available in this mode
+    #[clap(long)]
+    stdin: bool,
+
+    /// Replay a JSON-lines chat log. Can be repeated and labeled like --video-id
+    #[clap(long)]
+    replay: Vec<String>,
 
-    /// YouTube channel ID or username
-    #[clap(short, long, group = "target")]
-    channel_id: Option<String>,
+    /// Replay speed multiplier; 0 replays without pauses
+    #[clap(long, default_value_t = 1.0)]
+    replay_speed: f64,
+
+    /// WebSocket URL that sends JSON chat messages. Can be repeated and
+    /// labeled like --video-id
+    #[clap(long)]
+    websocket: Vec<String>,
 
     /// Path to config file (optional)
     #[clap(short, long)]
     config: Option<String>,
 
-    /// TTS engine to use (windows or openai)
-    #[clap(long)]
-    tts_engine: Option<String>,
+    /// Override a config setting, e.g. `--set filter.max_length=100`. Can be
+    /// repeated; takes precedence over the file and YTTTS_* variables
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
+    /// TTS engine to use
+    #[clap(long, value_enum)]
+    tts_engine: Option<TtsEngine>,
 
     /// OpenAI voice to use (if tts-engine is openai)
     #[clap(long)]
     openai_voice: Option<String>,
+
+    /// File to save the chat position in, so a restart continues where it left off
+    #[clap(long)]
+    state_file: Option<String>,
+
+    /// Ignore chat messages published before the bot started
+    #[clap(long)]
+    skip_backlog: bool,
+
+    /// Wait for the stream to go live instead of failing if it hasn't started
+    #[clap(long)]
+    wait: bool,
+
+    /// Keep running when a stream ends and attach to the channel's next stream
+    #[clap(long)]
+    follow: bool,
+
+    /// Append raw chat items, messages and events to a JSON-lines file
+    #[clap(long)]
+    record: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Read a chat recording aloud through the filters and TTS, with its
+    /// original timing
+    Replay {
+        /// Recording made with --record
+        file: String,
+
+        /// Speed multiplier; 0 replays without pauses
+        #[clap(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+
+    /// Report every problem in the config file without starting the bot
+    CheckConfig,
+
+    /// Search the log of what was read aloud, filtered or dropped
+    History {
+        /// Messages by this author
+        #[clap(long)]
+        author: Option<String>,
+
+        /// Messages received on this date (YYYY-MM-DD, local time)
+        #[clap(long)]
+        date: Option<chrono::NaiveDate>,
+
+        /// Messages containing this text
+        #[clap(long)]
+        keyword: Option<String>,
+
+        /// Only entries with this disposition: spoken, skipped, filtered,
+        /// dropped or failed
+        #[clap(long)]
+        disposition: Option<String>,
+
+        /// History file to read instead of the configured one
+        #[clap(long)]
+        file: Option<String>,
+    },
+}
+
+fn check_config(config_path: Option<&str>, overrides: &Overrides) -> Result<()> {
+    let name = match config::find_config_file(config_path) {
+        Ok(path) => path.display().to_string(),
+        Err(_) => "Configuration".to_string(),
+    };
+    let table = config::load_table(config_path, overrides)?;
+    let mut problems = validation::check(&table);
+
+    // Installed voices can only be listed on the machine that will speak
+    if let Ok(config) = config::config_from_table(&table) {
+        let engines = std::iter::once(&config.tts_engine).chain(&config.fallback_engines);
+        if engines.into_iter().any(|e| *e == TtsEngine::Windows) {
+            match tts::windows_voice_names() {
+                Ok(voices) => problems.extend(validation::check_windows_voice(&config, &voices)),
+                Err(e) => tracing::warn!("Could not list Windows voices: {}", e),
+            }
+        }
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    let errors = problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(anyhow::anyhow!("{} error(s) in {}", errors, name));
+    }
+    println!("{}: OK", name);
+    Ok(())
+}
+
+// Build the engine a reloaded config needs before changing anything, so a
+// config whose engine fails to start leaves the session as it was
+fn reload_config(session: &mut Session, config: Config) -> Result<String> {
+    let engine = if session.needs_new_engine(&config) {
+        Some(tts::create_tts_engine(&config)?)
+    } else {
+        None
+    };
+    Ok(session.reload_config(config, engine))
+}
+
+fn print_history(path: &Path, query: &HistoryQuery) -> Result<()> {
+    for entry in history::query(path, query)? {
+        println!("{}", history::format_entry(&entry));
+    }
+    Ok(())
+}
+
+// Options shared by every watched stream
+#[derive(Debug, Clone)]
+struct WatchOptions {
+    skip_backlog: bool,
+    wait: bool,
+    follow: bool,
+    state_file: Option<PathBuf>,
+}
+
+// Split a `label=target` argument. URLs contain '=' too, so only a plain word
+// before it is taken as a label; otherwise the argument is its own label.
+fn parse_source(arg: &str) -> (String, &str) {
+    match arg.split_once('=') {
+        Some((label, value))
+            if !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_') =>
+        {
+            (label.to_string(), value)
+        }
+        _ => (arg.to_string(), arg),
+    }
+}
+
+// Give every source its own state file next to the configured one
+fn state_file_for(path: &str, label: &str, multiple: bool) -> PathBuf {
+    let path = PathBuf::from(path);
+    if !multiple {
+        return path;
+    }
+
+    let label: String = label
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, label, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, label),
+    };
+    path.with_file_name(file_name)
+}
+
+// Tag messages from a source with its label and pass them on until the source
+// ends. Messages that already name their source, like replayed recordings,
+// keep it. Returns false once nothing is receiving anymore.
+async fn forward_messages(
+    label: &str,
+    source: &mut dyn ChatSource,
+    recorder: Option<&Recorder>,
+    message_tx: &mpsc::Sender<youtube::ChatMessage>,
+) -> Result<bool> {
+    while let Some(mut message) = source.next_message().await? {
+        if message.source.is_empty() {
+            message.source = label.to_string();
+        }
+        if let Some(recorder) = recorder {
+            recorder.record_message(&message);
+        }
+        if message_tx.send(message).await.is_err() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Read chat for the target and forward its messages, re-attaching to new
+// streams in follow mode
+async fn watch_chat(
+    label: String,
+    target: Target,
+    options: WatchOptions,
+    config: Config,
+    quota: QuotaTracker,
+    recorder: Option<Recorder>,
+    message_tx: mpsc::Sender<youtube::ChatMessage>,
+) -> Result<()> {
+    let client = reqwest::Client::new();
+    let wait_interval = Duration::from_secs(config.wait_poll_interval_secs);
+
+    loop {
+        let video_id = match &target {
+            _ if options.wait || options.follow => {
+                youtube::ChatMonitor::wait_for_live_video(
+                    &client,
+                    &target,
+                    &config.api_key,
+                    Some(&quota),
+                    wait_interval,
+                )
+                .await?
+            }
+            Target::Video(vid) => {
+                tracing::info!("Using provided video ID: {}", vid);
+                vid.clone()
+            }
+            _ => {
+                tracing::info!("Searching for live stream for channel: {:?}", target);
+                youtube::ChatMonitor::resolve_video_id(
+                    &client,
+                    &target,
+                    &config.api_key,
+                    Some(&quota),
+                )
+                .await?
+            }
+        };
+
+        let mut chat_monitor = youtube::ChatMonitor::new(&video_id, &config.api_key)?;
+        chat_monitor.set_poll_interval(config.poll_interval_ms);
+        chat_monitor.set_retry_policy(config.max_retries, config.max_retry_delay_ms);
+        chat_monitor.set_quota_tracker(quota.clone());
+        chat_monitor.set_backlog_policy(config.backlog_max_age_secs, config.skip_initial_page);
+        chat_monitor.set_skip_backlog(options.skip_backlog);
+        if let Some(recorder) = &recorder {
+            chat_monitor.set_recorder(recorder.clone());
+        }
+        if let Some(path) = &options.state_file {
+            chat_monitor.set_state_file(path);
+        }
+        tracing::info!("Monitoring chat for video ID: {} ({})", video_id, label);
+
+        if !forward_messages(&label, &mut chat_monitor, recorder.as_ref(), &message_tx).await? {
+            return Ok(());
+        }
+
+        if !options.follow || matches!(target, Target::Video(_)) {
+            return Ok(());
+        }
+
+        // The ended stream can stay listed as live for a little while
+        tracing::info!("Stream ended, waiting for the next one");
+        tokio::time::sleep(wait_interval).await;
+    }
 }
 
 #[tokio::main]
@@ -43,51 +351,247 @@ async fn main() -> Result<()> {
     let args = Args::parse();
     tracing::info!("Starting YouTube Live TTS Bot");
 
-    let mut config = config::load_config(args.config.as_deref())?;
+    let mut overrides = Overrides::from_env();
+    for arg in &args.overrides {
+        overrides.parse_arg(arg)?;
+    }
+    // As overrides, so they are validated and kept when the config is reloaded
     if let Some(engine) = args.tts_engine {
-        match engine.to_lowercase().as_str() {
-            "windows" => config.tts_engine = TtsEngine::Windows,
-            "openai" => config.tts_engine = TtsEngine::OpenAI,
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "Invalid TTS engine: {}. Supported engines: windows, openai",
-                    engine
-                ));
-            }
-        }
+        overrides.set("tts_engine", engine.name());
+    }
+    if let Some(voice) = &args.openai_voice {
+        overrides.set("openai_voice", voice.as_str());
+    }
+
+    if let Some(Command::CheckConfig) = &args.command {
+        return check_config(args.config.as_deref(), &overrides);
     }
 
-    if let Some(voice) = args.openai_voice {
-        config.openai_voice = voice;
+    if let Some(Command::History {
+        author,
+        date,
+        keyword,
+        disposition,
+        file,
+    }) = &args.command
+    {
+        let query = HistoryQuery {
+            author: author.clone(),
+            date: *date,
+            keyword: keyword.clone(),
+            disposition: disposition.clone(),
+        };
+        // Only the configured history file needs the config
+        let path = match file {
+            Some(file) => PathBuf::from(file),
+            None => config::load_config_with(args.config.as_deref(), &overrides)?
+                .history_path()
+                .ok_or_else(|| anyhow::anyhow!("No history file configured"))?,
+        };
+        return print_history(&path, &query);
     }
 
+    // A replay only needs TTS settings, so it can run without a config file
+    let config = if let Some(Command::Replay { .. }) = &args.command {
+        config::load_config_or_defaults(args.config.as_deref(), &overrides)?
+    } else {
+        config::load_config_with(args.config.as_deref(), &overrides)?
+    };
+
     tracing::info!("Initializing TTS engine: {:?}", config.tts_engine);
     let tts_engine = tts::create_tts_engine(&config)?;
 
-    let video_id = match (args.video_id, args.channel_id) {
-        (Some(vid), _) => {
-            tracing::info!("Using provided video ID: {}", vid);
-            vid
-        }
-        (_, Some(channel)) => {
-            tracing::info!("Searching for live stream for channel: {}", channel);
-            let client = reqwest::Client::new();
-            youtube::ChatMonitor::find_live_video_id_by_channel(&client, &channel, &config.api_key)
-                .await?
+    let quota = config.quota_tracker();
+    let history = config.history()?;
+
+    // Both arguments also accept URLs and @handles
+    let mut sources = Vec::new();
+    for arg in &args.video_id {
+        let (label, value) = parse_source(arg);
+        sources.push((label, Target::from_video_arg(value)));
+    }
+    for arg in &args.channel_id {
+        let (label, value) = parse_source(arg);
+        sources.push((label, Target::from_channel_arg(value)));
+    }
+
+    if !sources.is_empty() && config.api_key.is_empty() {
+        return Err(anyhow::anyhow!(
+            "api_key is required to read YouTube chats. Set it in the config file or YTTTS_API_KEY"
+        ));
+    }
+
+    // Sources that need no YouTube lookup
+    let mut other_sources: Vec<(String, Box<dyn ChatSource>)> = Vec::new();
+    for arg in &args.twitch {
+        let (label, channel) = parse_source(arg);
+        other_sources.push((label, Box::new(TwitchChat::new(channel))));
+    }
+    for arg in &args.websocket {
+        let (label, url) = parse_source(arg);
+        other_sources.push((label, Box::new(WebSocketSource::new(url))));
+    }
+    for arg in &args.replay {
+        let (label, path) = parse_source(arg);
+        let replay = ReplaySource::open(path, args.replay_speed).await?;
+        other_sources.push((label, Box::new(replay)));
+    }
+    if args.stdin {
+        other_sources.push(("stdin".to_string(), Box::new(StdinSource::new())));
+    }
+    if let Some(Command::Replay { file, speed }) = &args.command {
+        let replay = ReplaySource::open(file, *speed).await?;
+        other_sources.push(("replay".to_string(), Box::new(replay)));
+    }
+
+    if sources.is_empty() && other_sources.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Either --video-id, --channel-id or another chat source must be provided"
+        ));
+    }
+
+    let mut labels = HashSet::new();
+    for label in sources
+        .iter()
+        .map(|(label, _)| label)
+        .chain(other_sources.iter().map(|(label, _)| label))
+    {
+        if !labels.insert(label) {
+            return Err(anyhow::anyhow!("Duplicate source label: {}", label));
         }
-        _ => {
-            return Err(anyhow::anyhow!(
-                "Either --video-id or --channel-id must be provided"
-            ));
+    }
+
+    let recorder = args.record.as_deref().map(Recorder::create).transpose()?;
+
+    // Poll chat in separate tasks so console commands are handled while waiting.
+    // All sources feed the same channel.
+    let (message_tx, mut message_rx) = mpsc::channel(100);
+    let mut watchers = JoinSet::new();
+    let multiple = sources.len() > 1;
+    for (label, target) in sources {
+        if args.follow && matches!(target, Target::Video(_)) {
+            tracing::warn!(
+                "--follow needs a channel to find the next stream on; {} stops when its stream ends",
+                label
+            );
         }
+
+        let options = WatchOptions {
+            skip_backlog: args.skip_backlog,
+            wait: args.wait,
+            follow: args.follow,
+            state_file: args
+                .state_file
+                .as_deref()
+                .map(|path| state_file_for(path, &label, multiple)),
+        };
+        watchers.spawn(watch_chat(
+            label,
+            target,
+            options,
+            config.clone(),
+            quota.clone(),
+            recorder.clone(),
+            message_tx.clone(),
+        ));
+    }
+    for (label, mut source) in other_sources {
+        let recorder = recorder.clone();
+        let message_tx = message_tx.clone();
+        watchers.spawn(async move {
+            forward_messages(&label, source.as_mut(), recorder.as_ref(), &message_tx).await?;
+            Ok(())
+        });
+    }
+    drop(message_tx);
+
+    // stdin can carry chat messages or console commands, not both
+    let (_console_tx, mut commands) = if args.stdin {
+        tracing::info!("Reading chat from stdin; console commands are disabled");
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Some(tx), rx)
+    } else {
+        tracing::info!("Type 'help' for console commands");
+        (None, console::spawn_stdin_reader())
     };
+    let mut config_watcher = if config.watch_config {
+        ConfigWatcher::new(args.config.as_deref(), overrides)
+    } else {
+        None
+    };
+    if let Some(watcher) = &config_watcher {
+        tracing::info!("Watching {} for changes", watcher.path().display());
+    }
 
-    let mut chat_monitor = youtube::ChatMonitor::new(&video_id, &config.api_key)?;
-    chat_monitor.set_poll_interval(config.poll_interval_ms);
-    tracing::info!("Monitoring chat for video ID: {}", video_id);
-    while let Some(message) = chat_monitor.next_message().await? {
+    let mut session = Session::new(config, tts_engine);
+    session.set_quota_tracker(quota.clone());
+    if let Some(history) = history {
+        session.set_history(history);
+    }
+    let mut speech_tick = tokio::time::interval(Duration::from_millis(SPEECH_TICK_MS));
+    let mut config_tick = tokio::time::interval(Duration::from_secs(CONFIG_POLL_SECS));
+
+    loop {
+        tokio::select! {
+            Some(result) = watchers.join_next() => {
+                // A failing stream only stops the bot if nothing else is left to read
+                match result? {
+                    Err(e) if watchers.is_empty() => return Err(e),
+                    Err(e) => tracing::error!("Stopped reading a stream: {}", e),
+                    Ok(()) => {}
+                }
+                if watchers.is_empty() {
+                    break;
+                }
+            }
+            Some(message) = message_rx.recv() => {
+                tracing::info!("New message from {}: {}", message.author, message.text);
+                session.enqueue_message(message);
+            }
+            Some(line) = commands.recv() => {
+                let response = console::parse_command(&line)
+                    .and_then(|command| command.map(|c| session.execute(c)).transpose());
+                match response {
+                    Ok(Some(response)) => println!("{}", response),
+                    Ok(None) => {}
+                    Err(e) => println!("{}", e),
+                }
+            }
+            _ = speech_tick.tick() => {
+                session.tick()?;
+            }
+            _ = config_tick.tick(), if config_watcher.is_some() => {
+                if let Some(result) = config_watcher.as_mut().and_then(|w| w.poll()) {
+                    match result.and_then(|config| reload_config(&mut session, config)) {
+                        Ok(summary) => tracing::info!("Config reloaded: {}", summary),
+                        Err(e) => tracing::error!(
+                            "Config reload rejected, keeping the current settings: {}",
+                            e
+                        ),
+                    }
+                }
+            }
+        }
+    }
+
+    // Every sender has finished, so this takes the messages still in the
+    // channel and then ends
+    while let Some(message) = message_rx.recv().await {
         tracing::info!("New message from {}: {}", message.author, message.text);
-        tts_engine.speak(&format!("{}さん: {}", message.author, message.text))?;
+        session.enqueue_message(message);
+    }
+
+    // Finish reading what is already queued, e.g. the end of a replay
+    while session.has_pending_speech() {
+        speech_tick.tick().await;
+        session.tick()?;
+    }
+
+    quota.save();
+
+    let usage = TtsUsage::session();
+    if usage.characters() > 0 {
+        tracing::info!("TTS usage this session: {}", usage.summary());
     }
 
     Ok(())
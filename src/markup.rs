@@ -0,0 +1,221 @@
+use crate::source::ChatMessage;
+use crate::tts::Prosody;
+
+// Template placeholders and the parts of a message they stand for
+const PLACEHOLDERS: [&str; 4] = ["{author}", "{text}", "{source}", "{amount}"];
+
+/// One part of a text to be spoken
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Text(String),
+    Author(String),
+    /// A paid message amount, e.g. "¥500" or "100 bits"
+    Amount(String),
+}
+
+/// Text to be spoken, with the parts of a chat message marked so engines that
+/// accept SSML can read them differently. Engines render it to SSML or to
+/// plain text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Markup {
+    segments: Vec<Segment>,
+}
+
+impl Markup {
+    /// Text with no marked parts, e.g. from the `say` command
+    pub fn plain(text: &str) -> Self {
+        let mut markup = Self::default();
+        markup.push(Segment::Text(text.to_string()));
+        markup
+    }
+
+    /// Fill in a message template. {author}, {text}, {source} and {amount}
+    /// are replaced; values are never searched for placeholders themselves.
+    pub fn render(template: &str, message: &ChatMessage) -> Self {
+        let mut markup = Self::default();
+        let mut rest = template;
+
+        loop {
+            let next = PLACEHOLDERS
+                .iter()
+                .filter_map(|p| rest.find(p).map(|i| (i, *p)))
+                .min();
+            let Some((index, placeholder)) = next else {
+                markup.push(Segment::Text(rest.to_string()));
+                break;
+            };
+
+            markup.push(Segment::Text(rest[..index].to_string()));
+            match placeholder {
+                "{author}" => markup.push(Segment::Author(message.author.clone())),
+                "{text}" => markup.push(Segment::Text(message.text.clone())),
+                "{source}" => markup.push(Segment::Text(message.source.clone())),
+                _ => {
+                    if let Some(amount) = &message.amount {
+                        markup.push(Segment::Amount(amount.clone()));
+                    }
+                }
+            }
+            rest = &rest[index + placeholder.len()..];
+        }
+
+        markup
+    }
+
+    // Adjacent text is merged and empty parts are dropped
+    fn push(&mut self, segment: Segment) {
+        match (&segment, self.segments.last_mut()) {
+            (Segment::Text(text), _) | (Segment::Author(text), _) | (Segment::Amount(text), _)
+                if text.is_empty() => {}
+            (Segment::Text(text), Some(Segment::Text(last))) => last.push_str(text),
+            _ => self.segments.push(segment),
+        }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn to_plain(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) | Segment::Author(text) | Segment::Amount(text) => {
+                    text.as_str()
+                }
+            })
+            .collect()
+    }
+
+    /// A complete SSML document in language `lang`, e.g. "ja-JP", reading
+    /// author names with `author` prosody. All text is escaped.
+    pub fn to_ssml(&self, lang: &str, author: Prosody) -> String {
+        let mut ssml = format!(
+            r#"<speak version="1.0" xmlns="http://www.w3.org/2001/10/synthesis" xml:lang="{}">"#,
+            escape(lang)
+        );
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => ssml.push_str(&escape(text)),
+                Segment::Author(name) if author == Prosody::default() => {
+                    ssml.push_str(&escape(name))
+                }
+                Segment::Author(name) => ssml.push_str(&format!(
+                    r#"<prosody rate="{}" pitch="{}" volume="{}">{}</prosody>"#,
+                    percent(author.rate),
+                    relative_percent(author.pitch),
+                    relative_percent(author.volume),
+                    escape(name)
+                )),
+                Segment::Amount(amount) => ssml.push_str(&amount_ssml(amount)),
+            }
+        }
+
+        ssml.push_str("</speak>");
+        ssml
+    }
+}
+
+// Currency amounts are read as money; Twitch bits as a count followed by "bits"
+fn amount_ssml(amount: &str) -> String {
+    match amount.split_once(' ') {
+        Some((count, unit)) if count.chars().all(|c| c.is_ascii_digit() || c == ',') => {
+            format!(
+                r#"<say-as interpret-as="cardinal">{}</say-as> {}"#,
+                escape(count),
+                escape(unit)
+            )
+        }
+        _ => format!(
+            r#"<say-as interpret-as="currency">{}</say-as>"#,
+            escape(amount)
+        ),
+    }
+}
+
+fn percent(value: f32) -> String {
+    format!("{:.0}%", value * 100.0)
+}
+
+fn relative_percent(value: f32) -> String {
+    format!("{:+.0}%", (value - 1.0) * 100.0)
+}
+
+/// Escape text for use in XML content and attribute values. Characters XML
+/// doesn't allow at all, such as most control characters, are dropped.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\0'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {}
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(author: &str, text: &str, amount: Option<&str>) -> ChatMessage {
+        ChatMessage {
+            id: "1".to_string(),
+            author: author.to_string(),
+            text: text.to_string(),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            source: String::new(),
+            roles: Vec::new(),
+            amount: amount.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn renders_plain_text_and_escaped_ssml() {
+        let message = message("{text}", "<break time=\"10s\"/> & hi", Some("¥500"));
+        let markup = Markup::render("{author}: {amount} {text}", &message);
+
+        assert_eq!(markup.to_plain(), "{text}: ¥500 <break time=\"10s\"/> & hi");
+
+        let author = Prosody {
+            rate: 1.1,
+            pitch: 0.9,
+            volume: 1.0,
+        };
+        let ssml = markup.to_ssml("ja-JP", author);
+        assert!(ssml.starts_with(r#"<speak version="1.0""#));
+        assert!(ssml.contains(r#"<prosody rate="110%" pitch="-10%" volume="+0%">{text}</prosody>"#));
+        assert!(ssml.contains(r#"<say-as interpret-as="currency">¥500</say-as>"#));
+        assert!(ssml.contains("&lt;break time=&quot;10s&quot;/&gt; &amp; hi"));
+        assert!(!ssml.contains("<break"));
+    }
+
+    #[test]
+    fn drops_characters_xml_forbids() {
+        assert_eq!(escape("a\u{1}b\u{8}\u{b}\u{c}c\u{ffff}"), "abc");
+        assert_eq!(escape("line\tone\nline two\r"), "line\tone\nline two\r");
+        assert_eq!(escape("絵文字 🎉"), "絵文字 🎉");
+    }
+
+    #[test]
+    fn reads_bits_as_a_count() {
+        let markup = Markup::render("{amount}", &message("a", "", Some("100 bits")));
+        assert!(markup
+            .to_ssml("en-US", Prosody::default())
+            .contains(r#"<say-as interpret-as="cardinal">100</say-as> bits"#));
+
+        // Messages without an amount leave the placeholder empty
+        let markup = Markup::render("{author}{amount}!", &message("a", "", None));
+        assert_eq!(
+            markup.segments(),
+            [Segment::Author("a".into()), Segment::Text("!".into())]
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+    vec,
+};
+
+use anyhow::Result;
+use rodio::{OutputStream, Sink, Source};
+
+use crate::error::AppError;
+
+type BoxedSource = Box<dyn Source<Item = i16> + Send>;
+
+enum PlayerCommand {
+    Append(BoxedSource),
+    Pause,
+    Resume,
+    StopCurrent,
+    Clear,
+}
+
+// Opened on first use and kept for the rest of the session
+static SHARED_PLAYER: Mutex<Option<Player>> = Mutex::new(None);
+
+/// Handle to a playback thread that keeps one audio output open and plays
+/// appended sources one after another.
+#[derive(Clone)]
+pub struct Player {
+    commands: mpsc::Sender<PlayerCommand>,
+}
+
+impl Player {
+    /// The player for the default output device, shared by all engines
+    pub fn shared() -> Result<Self> {
+        let mut shared = SHARED_PLAYER.lock().expect("player lock poisoned");
+        if let Some(player) = shared.as_ref() {
+            return Ok(player.clone());
+        }
+
+        let player = Self::spawn()?;
+        *shared = Some(player.clone());
+        Ok(player)
+    }
+
+    /// The shared player, if anything has been played yet
+    pub fn opened() -> Option<Self> {
+        SHARED_PLAYER.lock().expect("player lock poisoned").clone()
+    }
+
+    /// Open the default output device on a new playback thread
+    pub fn spawn() -> Result<Self> {
+        let (commands, receiver) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("playback".to_string())
+            .spawn(move || {
+                // The output stream can't be sent between threads, so it is
+                // opened here and lives as long as the thread
+                let opened = OutputStream::try_default()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|(stream, handle)| Ok((stream, Sink::try_new(&handle)?)));
+                let (_stream, sink) = match opened {
+                    Ok(opened) => {
+                        let _ = ready_tx.send(Ok(()));
+                        opened
+                    }
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+
+                run(&sink, receiver);
+            })
+            .map_err(AppError::IO)?;
+
+        ready_rx
+            .recv()
+            .map_err(|_| AppError::Tts("Playback thread exited".to_string()))??;
+        tracing::debug!("Opened audio output");
+
+        Ok(Self { commands })
+    }
+
+    /// Queue a source to play after everything appended before it
+    pub fn append(&self, source: impl Source<Item = i16> + Send + 'static) -> Result<()> {
+        self.send(PlayerCommand::Append(Box::new(source)))
+    }
+
+    pub fn pause(&self) -> Result<()> {
+        self.send(PlayerCommand::Pause)
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        self.send(PlayerCommand::Resume)
+    }
+
+    /// Skip the source that is playing and continue with the next one
+    pub fn stop_current(&self) -> Result<()> {
+        self.send(PlayerCommand::StopCurrent)
+    }
+
+    /// Drop everything that is playing or queued
+    pub fn clear(&self) -> Result<()> {
+        self.send(PlayerCommand::Clear)
+    }
+
+    fn send(&self, command: PlayerCommand) -> Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| AppError::Tts("Playback thread has stopped".to_string()).into())
+    }
+}
+
+fn run(sink: &Sink, commands: mpsc::Receiver<PlayerCommand>) {
+    for command in commands {
+        match command {
+            PlayerCommand::Append(source) => sink.append(source),
+            PlayerCommand::Pause => sink.pause(),
+            PlayerCommand::Resume => sink.play(),
+            PlayerCommand::StopCurrent => sink.skip_one(),
+            PlayerCommand::Clear => {
+                // Clearing also pauses the sink
+                let paused = sink.is_paused();
+                sink.clear();
+                if !paused {
+                    sink.play();
+                }
+            }
+        }
+    }
+    tracing::debug!("Closed audio output");
+}
+
+/// Change the pitch and volume of a source while it plays, for engines that
+/// can't synthesize with them. Resampling changes the tempo along with the
+/// pitch, so callers compensate in the rate they synthesize at.
+pub fn adjust<S>(source: S, pitch: f32, volume: f32) -> impl Source<Item = i16> + Send
+where
+    S: Source<Item = i16> + Send + 'static,
+{
+    source.speed(pitch).amplify(volume)
+}
+
+/// Mono 16-bit audio that can start playing while it is still arriving.
+/// Plays silence when the data runs short, and ends once the sender is
+/// dropped and everything received has played, or when `stop` is set.
+pub struct PcmStream {
+    samples: mpsc::Receiver<Vec<i16>>,
+    current: vec::IntoIter<i16>,
+    sample_rate: u32,
+    stop: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+}
+
+impl PcmStream {
+    pub fn new(sample_rate: u32, samples: mpsc::Receiver<Vec<i16>>, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            samples,
+            current: Vec::new().into_iter(),
+            sample_rate,
+            stop,
+            finished: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Set once the stream has ended, been skipped or been dropped unplayed
+    pub fn finished(&self) -> Arc<AtomicBool> {
+        self.finished.clone()
+    }
+}
+
+// The player drops a source as soon as it is done with it
+impl Drop for PcmStream {
+    fn drop(&mut self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Iterator for PcmStream {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.stop.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        loop {
+            if let Some(sample) = self.current.next() {
+                return Some(sample);
+            }
+            match self.samples.try_recv() {
+                Ok(chunk) => self.current = chunk.into_iter(),
+                Err(mpsc::TryRecvError::Empty) => return Some(0),
+                Err(mpsc::TryRecvError::Disconnected) => return None,
+            }
+        }
+    }
+}
+
+impl Source for PcmStream {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcm_stream_fills_gaps_with_silence() {
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut stream = PcmStream::new(24_000, rx, stop.clone());
+        let finished = stream.finished();
+
+        tx.send(vec![1, 2]).unwrap();
+        assert_eq!(stream.next(), Some(1));
+        assert_eq!(stream.next(), Some(2));
+        assert_eq!(stream.next(), Some(0));
+
+        tx.send(vec![3]).unwrap();
+        drop(tx);
+        assert_eq!(stream.next(), Some(3));
+        assert_eq!(stream.next(), None);
+
+        assert!(!finished.load(Ordering::SeqCst));
+        drop(stream);
+        assert!(finished.load(Ordering::SeqCst));
+
+        let (_tx, rx) = mpsc::channel();
+        let mut stopped = PcmStream::new(24_000, rx, stop.clone());
+        stop.store(true, Ordering::SeqCst);
+        assert_eq!(stopped.next(), None);
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+
+// Plan for at least this much more streaming even once the expected length has passed
+const MIN_REMAINING_STREAM: Duration = Duration::from_secs(30 * 60);
+
+// Usage is saved after this many more units, rather than on every call. A
+// crash loses at most this much of the estimate.
+const SAVE_EVERY_UNITS: u64 = 50;
+
+/// YouTube Data API endpoints used by the bot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Videos,
+    Channels,
+    Search,
+    PlaylistItems,
+    LiveChatMessages,
+}
+
+impl Endpoint {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Endpoint::Videos => "videos.list",
+            Endpoint::Channels => "channels.list",
+            Endpoint::Search => "search.list",
+            Endpoint::PlaylistItems => "playlistItems.list",
+            Endpoint::LiveChatMessages => "liveChatMessages.list",
+        }
+    }
+
+    /// Quota units charged per call, from the YouTube Data API quota calculator
+    pub fn cost(&self) -> u64 {
+        match self {
+            Endpoint::Videos | Endpoint::Channels | Endpoint::PlaylistItems => 1,
+            Endpoint::Search => 100,
+            Endpoint::LiveChatMessages => 5,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QuotaUsage {
+    date: String,
+    used: BTreeMap<String, u64>,
+}
+
+impl QuotaUsage {
+    fn total(&self) -> u64 {
+        self.used.values().sum()
+    }
+}
+
+struct QuotaState {
+    usage: QuotaUsage,
+    // Total in the usage file
+    saved_total: u64,
+    warned: bool,
+    last_interval_ms: u64,
+}
+
+/// Estimated YouTube API quota spend for the current day, shared between
+/// everything that calls the API and optionally persisted to disk.
+#[derive(Clone)]
+pub struct QuotaTracker {
+    state: Arc<Mutex<QuotaState>>,
+    path: Option<PathBuf>,
+    budget: u64,
+    warn_percent: u64,
+    started_at: Instant,
+    expected_stream: Duration,
+}
+
+// The quota resets at midnight Pacific Time. DST is not taken into account,
+// so the day may roll over an hour late in summer.
+fn quota_date() -> String {
+    let pacific = FixedOffset::west_opt(8 * 3600).expect("valid offset");
+    Utc::now()
+        .with_timezone(&pacific)
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+pub fn default_usage_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("youtube-live-tts/quota.json"))
+}
+
+impl QuotaTracker {
+    pub fn new(
+        path: Option<PathBuf>,
+        budget: u64,
+        warn_percent: u64,
+        expected_stream: Duration,
+    ) -> Self {
+        let usage = path
+            .as_ref()
+            .and_then(|p| match Self::load_usage(p) {
+                Ok(usage) => usage,
+                Err(e) => {
+                    tracing::warn!("Failed to read quota usage from {:?}: {}", p, e);
+                    None
+                }
+            })
+            .filter(|usage| usage.date == quota_date())
+            .unwrap_or_else(|| QuotaUsage {
+                date: quota_date(),
+                used: BTreeMap::new(),
+            });
+
+        Self {
+            state: Arc::new(Mutex::new(QuotaState {
+                saved_total: usage.total(),
+                usage,
+                warned: false,
+                last_interval_ms: 0,
+            })),
+            path,
+            budget,
+            warn_percent,
+            started_at: Instant::now(),
+            expected_stream,
+        }
+    }
+
+    fn load_usage(path: &PathBuf) -> Result<Option<QuotaUsage>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    // Serialized while the lock is held and written after it is released, so
+    // API calls on other tasks don't wait for the disk
+    fn snapshot(&self, state: &mut QuotaState) -> Option<String> {
+        self.path.as_ref()?;
+        state.saved_total = state.usage.total();
+        match serde_json::to_string_pretty(&state.usage) {
+            Ok(json) => Some(json),
+            Err(e) => {
+                tracing::warn!("Failed to save quota usage: {}", e);
+                None
+            }
+        }
+    }
+
+    fn write_snapshot(&self, json: &str) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, json));
+        if let Err(e) = result {
+            tracing::warn!("Failed to save quota usage: {}", e);
+        }
+    }
+
+    /// Write usage not yet saved to the usage file
+    pub fn save(&self) {
+        let snapshot = {
+            let mut state = self.state.lock().unwrap();
+            if state.saved_total == state.usage.total() {
+                return;
+            }
+            self.snapshot(&mut state)
+        };
+        if let Some(json) = snapshot {
+            self.write_snapshot(&json);
+        }
+    }
+
+    pub fn record(&self, endpoint: Endpoint) {
+        let mut state = self.state.lock().unwrap();
+
+        let today = quota_date();
+        let new_day = state.usage.date != today;
+        if new_day {
+            state.usage = QuotaUsage {
+                date: today,
+                used: BTreeMap::new(),
+            };
+            state.warned = false;
+        }
+
+        *state
+            .usage
+            .used
+            .entry(endpoint.name().to_string())
+            .or_default() += endpoint.cost();
+
+        let used = state.usage.total();
+        if !state.warned && used * 100 >= self.budget * self.warn_percent {
+            state.warned = true;
+            tracing::warn!(
+                "YouTube API quota usage is at {} of {} units ({}%)",
+                used,
+                self.budget,
+                used * 100 / self.budget.max(1)
+            );
+        }
+
+        let snapshot = if new_day || used >= state.saved_total + SAVE_EVERY_UNITS {
+            self.snapshot(&mut state)
+        } else {
+            None
+        };
+        drop(state);
+
+        if let Some(json) = snapshot {
+            self.write_snapshot(&json);
+        }
+    }
+
+    pub fn used(&self) -> u64 {
+        self.state.lock().unwrap().usage.total()
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.budget.saturating_sub(self.used())
+    }
+
+    /// Poll interval that keeps polling `endpoint` within the remaining quota
+    /// until the expected end of the stream, but never shorter than `configured_ms`.
+    pub fn poll_interval_ms(&self, configured_ms: u64, endpoint: Endpoint) -> u64 {
+        let remaining_stream = self
+            .expected_stream
+            .saturating_sub(self.started_at.elapsed())
+            .max(MIN_REMAINING_STREAM);
+        let polls = (self.remaining() / endpoint.cost()).max(1);
+        let needed_ms = (remaining_stream.as_millis() as u64) / polls;
+        let interval = configured_ms.max(needed_ms);
+
+        let mut state = self.state.lock().unwrap();
+        if interval > configured_ms && state.last_interval_ms <= configured_ms {
+            tracing::info!(
+                "Poll interval lengthened to {}ms to stay within the remaining quota ({} units)",
+                interval,
+                self.budget.saturating_sub(state.usage.total())
+            );
+        }
+        state.last_interval_ms = interval;
+
+        interval
+    }
+
+    pub fn summary(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let per_endpoint = state
+            .usage
+            .used
+            .iter()
+            .map(|(name, units)| format!("{} {}", name, units))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            "{} of {} units used today ({})",
+            state.usage.total(),
+            self.budget,
+            if per_endpoint.is_empty() {
+                "no calls yet"
+            } else {
+                &per_endpoint
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_total(path: &PathBuf) -> u64 {
+        QuotaTracker::load_usage(path)
+            .unwrap()
+            .map_or(0, |usage| usage.total())
+    }
+
+    #[test]
+    fn saves_in_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quota.json");
+        let tracker = QuotaTracker::new(Some(path.clone()), 10_000, 80, Duration::ZERO);
+
+        for _ in 0..9 {
+            tracker.record(Endpoint::LiveChatMessages);
+        }
+        assert_eq!(saved_total(&path), 0);
+        tracker.record(Endpoint::LiveChatMessages);
+        assert_eq!(saved_total(&path), 50);
+
+        tracker.record(Endpoint::Videos);
+        tracker.save();
+        assert_eq!(saved_total(&path), 51);
+
+        // A restart on the same day carries on from the saved usage
+        let restarted = QuotaTracker::new(Some(path), 10_000, 80, Duration::ZERO);
+        assert_eq!(restarted.used(), 51);
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::source::ChatMessage;
+
+/// One line of a chat recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// When the bot received the entry (RFC 3339)
+    pub received_at: String,
+    #[serde(flatten)]
+    pub entry: Entry,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    /// An item exactly as returned by the platform API
+    Raw {
+        source: String,
+        item: serde_json::Value,
+    },
+    /// A message as handed to the TTS pipeline
+    Message { message: ChatMessage },
+    /// Something that happened to a chat, like connecting or the chat ending
+    Event { source: String, event: String },
+}
+
+/// Appends everything received from chat to a JSON-lines file, so a show can
+/// be replayed later.
+#[derive(Clone)]
+pub struct Recorder {
+    writer: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(AppError::IO)?;
+
+        Ok(Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    pub fn record_raw(&self, source: &str, item: &serde_json::Value) {
+        self.write(Entry::Raw {
+            source: source.to_string(),
+            item: item.clone(),
+        });
+    }
+
+    pub fn record_message(&self, message: &ChatMessage) {
+        self.write(Entry::Message {
+            message: message.clone(),
+        });
+    }
+
+    pub fn record_event(&self, source: &str, event: &str) {
+        self.write(Entry::Event {
+            source: source.to_string(),
+            event: event.to_string(),
+        });
+    }
+
+    fn write(&self, entry: Entry) {
+        let record = Record {
+            received_at: Utc::now().to_rfc3339(),
+            entry,
+        };
+
+        let mut writer = self.writer.lock().expect("recorder lock poisoned");
+        // Flush every line so a crash loses nothing
+        let result = serde_json::to_writer(&mut *writer, &record)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(writer.write_all(b"\n")?))
+            .and_then(|()| Ok(writer.flush()?));
+        if let Err(e) = result {
+            tracing::warn!("Failed to write chat recording: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{ChatSource, ReplaySource};
+
+    #[tokio::test]
+    async fn recording_replays_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.jsonl");
+        let recorder = Recorder::create(&path).unwrap();
+
+        let message = ChatMessage {
+            id: "1".to_string(),
+            author: "viewer".to_string(),
+            text: "hello".to_string(),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            source: "main".to_string(),
+            roles: vec!["member".to_string()],
+            amount: Some("¥500".to_string()),
+        };
+        recorder.record_event("video", "connected");
+        recorder.record_raw("video", &serde_json::json!({"id": "1"}));
+        recorder.record_message(&message);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+        assert!(contents.contains(r#""kind":"raw""#));
+
+        let mut replay = ReplaySource::open(path.to_str().unwrap(), 0.0)
+            .await
+            .unwrap();
+        let replayed = replay.next_message().await.unwrap().unwrap();
+        assert_eq!(replayed.text, "hello");
+        assert_eq!(replayed.source, "main");
+        assert_eq!(replayed.amount, message.amount);
+        assert!(replay.next_message().await.unwrap().is_none());
+    }
+}
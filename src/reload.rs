@@ -0,0 +1,81 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Result;
+
+use crate::config::{self, Config, Overrides};
+
+// What a config file looked like when it was last loaded. The size catches
+// saves within the timestamp resolution of the file system.
+type Version = Option<(SystemTime, u64)>;
+
+fn version(path: &Path) -> Version {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Notices when the config file is saved and loads the new version, with the
+/// same overrides as at startup
+pub struct ConfigWatcher {
+    path: PathBuf,
+    overrides: Overrides,
+    version: Version,
+}
+
+impl ConfigWatcher {
+    /// Watch the config file in use, or `None` if there is no file because
+    /// every setting came from overrides
+    pub fn new(config_path: Option<&str>, overrides: Overrides) -> Option<Self> {
+        let path = config::find_config_file(config_path).ok()?;
+        Some(Self {
+            version: version(&path),
+            path,
+            overrides,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The new config if the file changed since it was last loaded. A file
+    /// that fails to load or validate is reported once per change.
+    pub fn poll(&mut self) -> Option<Result<Config>> {
+        let version = version(&self.path);
+        if version == self.version {
+            return None;
+        }
+        self.version = version;
+
+        let path = self.path.to_string_lossy();
+        Some(config::load_config_with(Some(&path), &self.overrides))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reloads_each_change_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "api_key = \"key\"\n").unwrap();
+
+        let mut watcher = ConfigWatcher::new(path.to_str(), Overrides::new()).unwrap();
+        assert!(watcher.poll().is_none());
+
+        fs::write(&path, "api_key = \"key\"\nrate = 1.5\n").unwrap();
+        let config = watcher.poll().unwrap().unwrap();
+        assert_eq!(config.rate, 1.5);
+        assert!(watcher.poll().is_none());
+
+        // Out of range, so the running settings should be kept
+        fs::write(&path, "api_key = \"key\"\nrate = 10.0\n").unwrap();
+        assert!(watcher.poll().unwrap().is_err());
+        assert!(watcher.poll().is_none());
+    }
+}
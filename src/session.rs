@@ -0,0 +1,817 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    time::Instant,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Utc};
+
+use crate::config::{Config, TtsEngine as TtsEngineType};
+use crate::console::{Command, HELP};
+use crate::filter::FilterReason;
+use crate::history::{Disposition, History, HistoryEntry};
+use crate::markup::Markup;
+use crate::quota::QuotaTracker;
+use crate::tts::{self, Prosody, TextToSpeech};
+use crate::youtube::ChatMessage;
+
+// Queue length at which the adaptive rate reaches max_rate
+const ADAPTIVE_RATE_QUEUE_LENGTH: usize = 10;
+
+// Settings read when the TTS engine is created; changing one on reload
+// creates a new engine
+const ENGINE_SETTINGS: [&str; 17] = [
+    "voice_name",
+    "tts_engine",
+    "fallback_engines",
+    "tts_timeout_secs",
+    "windows_voice",
+    "openai_api_key",
+    "openai_model",
+    "openai_voice",
+    "author_rate",
+    "author_pitch",
+    "openai_requests_per_minute",
+    "openai_max_retries",
+    "openai_pricing",
+    "openai_budget_usd",
+    "audio_cache_max_mb",
+    "audio_cache_dir",
+    "prefetch_concurrency",
+];
+
+// Settings only read at startup, by the chat readers, quota tracker and history
+const STARTUP_SETTINGS: [&str; 14] = [
+    "api_key",
+    "poll_interval_ms",
+    "backlog_max_age_secs",
+    "skip_initial_page",
+    "wait_poll_interval_secs",
+    "max_retries",
+    "max_retry_delay_ms",
+    "quota_budget",
+    "quota_warn_percent",
+    "expected_stream_hours",
+    "quota_usage_file",
+    "save_history",
+    "history_file",
+    "watch_config",
+];
+
+// A single piece of text waiting to be read aloud
+#[derive(Debug, Clone)]
+pub struct Utterance {
+    // The chat message being read, or None for text from the `say` command
+    pub message: Option<ChatMessage>,
+    pub speech: Markup,
+    // The speech as plain text, for filters, logs and the queue listing
+    pub text: String,
+    // Publish time of the chat message, used to keep the queue in order
+    pub published_at: Option<DateTime<FixedOffset>>,
+    pub received_at: DateTime<Utc>,
+    pub queued_at: Instant,
+}
+
+impl Utterance {
+    fn new(message: Option<ChatMessage>, speech: Markup) -> Self {
+        Self {
+            text: speech.to_plain(),
+            speech,
+            published_at: message
+                .as_ref()
+                .and_then(|m| DateTime::parse_from_rfc3339(&m.timestamp).ok()),
+            message,
+            received_at: Utc::now(),
+            queued_at: Instant::now(),
+        }
+    }
+
+    fn author(&self) -> Option<&str> {
+        self.message.as_ref().map(|m| m.author.as_str())
+    }
+
+    fn history_entry(&self, disposition: Disposition) -> HistoryEntry {
+        HistoryEntry {
+            message: self.message.clone(),
+            text: self.text.clone(),
+            voice: None,
+            disposition,
+            received_at: self.received_at.to_rfc3339(),
+            started_at: None,
+            queued_ms: None,
+            spoken_ms: None,
+        }
+    }
+}
+
+// The utterance currently being spoken
+struct Speech {
+    utterance: Utterance,
+    voice: String,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    skipped: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct SessionStats {
+    pub received: u64,
+    pub spoken: u64,
+    pub skipped: u64,
+    pub muted: u64,
+    pub filtered: u64,
+    pub dropped: u64,
+    pub failed: u64,
+}
+
+/// Speech queue and operator state for a running bot session
+pub struct Session {
+    config: Config,
+    engine: Box<dyn TextToSpeech>,
+    // Engine built by a `voice` command, swapped in once the current message ends
+    pending_engine: Option<Box<dyn TextToSpeech>>,
+    queue: VecDeque<Utterance>,
+    current: Option<Speech>,
+    muted: HashSet<String>,
+    paused: bool,
+    stats: SessionStats,
+    started_at: Instant,
+    quota: Option<QuotaTracker>,
+    history: Option<History>,
+}
+
+impl Session {
+    pub fn new(config: Config, engine: Box<dyn TextToSpeech>) -> Self {
+        Self {
+            config,
+            engine,
+            pending_engine: None,
+            queue: VecDeque::new(),
+            current: None,
+            muted: HashSet::new(),
+            paused: false,
+            stats: SessionStats::default(),
+            started_at: Instant::now(),
+            quota: None,
+            history: None,
+        }
+    }
+
+    pub fn set_quota_tracker(&mut self, quota: QuotaTracker) {
+        self.quota = Some(quota);
+    }
+
+    /// Log what happens to every message to `history`
+    pub fn set_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    pub fn enqueue_message(&mut self, message: ChatMessage) {
+        self.stats.received += 1;
+
+        let speech = Markup::render(self.config.message_template_for(&message.source), &message);
+
+        if self.is_muted(&message.author) {
+            tracing::debug!("Ignoring message from muted author {}", message.author);
+            self.stats.muted += 1;
+            self.record_filtered(
+                &Utterance::new(Some(message), speech),
+                FilterReason::MutedAuthor,
+            );
+            return;
+        }
+
+        let verdict = self
+            .config
+            .filters_for(&message.source)
+            .try_for_each(|filter| filter.check(&message));
+        if let Err(reason) = verdict {
+            tracing::debug!("Filtered message from {}: {}", message.author, reason);
+            self.stats.filtered += 1;
+            self.record_filtered(&Utterance::new(Some(message), speech), reason);
+            return;
+        }
+
+        let utterance = Utterance::new(Some(message), speech);
+
+        // Messages from several streams arrive in batches; keep the queue in
+        // publish order
+        let position = match utterance.published_at {
+            Some(published_at) => self
+                .queue
+                .iter()
+                .rposition(|u| u.published_at.is_none_or(|t| t <= published_at))
+                .map_or(0, |i| i + 1),
+            None => self.queue.len(),
+        };
+        self.queue.insert(position, utterance);
+
+        // Drop the oldest chat messages when chat outpaces speech
+        let max = self.config.max_queue_length;
+        while max > 0 && self.queue.len() > max {
+            let Some(index) = self.queue.iter().position(|u| u.message.is_some()) else {
+                break;
+            };
+            let Some(dropped) = self.queue.remove(index) else {
+                break;
+            };
+            tracing::debug!("Queue full, dropping: {}", dropped.text);
+            self.stats.dropped += 1;
+            let mut entry = dropped.history_entry(Disposition::Dropped);
+            entry.queued_ms = Some(dropped.queued_at.elapsed().as_millis() as u64);
+            self.record(&entry);
+        }
+    }
+
+    /// Start the next queued utterance if the engine is idle
+    pub fn tick(&mut self) -> Result<()> {
+        self.prefetch_upcoming();
+
+        if self.engine.is_speaking() {
+            // A fallback engine may have handed the message to another voice
+            if let (Some(speech), Some(voice)) = (&mut self.current, self.engine.current_voice()) {
+                speech.voice = voice;
+            }
+            return Ok(());
+        }
+
+        self.finish_speech();
+
+        if let Some(engine) = self.pending_engine.take() {
+            self.engine = engine;
+        }
+
+        if self.paused {
+            return Ok(());
+        }
+
+        // Measured before taking the message so it matches what was prefetched
+        let prosody = self.prosody();
+        if let Some(utterance) = self.queue.pop_front() {
+            tracing::info!("Speaking: {}", utterance.text);
+            let mut speech = Speech {
+                voice: self.voice().to_string(),
+                started_at: Utc::now(),
+                started: Instant::now(),
+                skipped: false,
+                utterance,
+            };
+
+            // A message that fails to synthesize is logged and the queue moves on
+            match self.engine.speak(&speech.utterance.speech, prosody) {
+                Ok(()) => {
+                    self.stats.spoken += 1;
+                    if let Some(voice) = self.engine.current_voice() {
+                        speech.voice = voice;
+                    }
+                    self.current = Some(speech);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to speak: {}", e);
+                    self.stats.failed += 1;
+                    let entry = Self::speech_entry(
+                        &speech,
+                        Disposition::Failed {
+                            error: e.to_string(),
+                        },
+                    );
+                    self.record(&entry);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Let the engine synthesize the next few messages while the current one plays
+    fn prefetch_upcoming(&self) {
+        let upcoming: Vec<String> = self
+            .queue
+            .iter()
+            .take(self.config.prefetch_count)
+            .map(|u| u.text.clone())
+            .collect();
+        self.engine.prefetch(&upcoming, self.prosody());
+    }
+
+    // Configured prosody, sped up with the queue length in adaptive mode
+    fn prosody(&self) -> Prosody {
+        let mut prosody = Prosody::from_config(&self.config);
+        if self.config.adaptive_rate && self.config.max_rate > prosody.rate {
+            let busy = self.queue.len().min(ADAPTIVE_RATE_QUEUE_LENGTH) as f32
+                / ADAPTIVE_RATE_QUEUE_LENGTH as f32;
+            // In steps of 0.25, so prefetched and cached audio stays usable
+            // while the queue length changes
+            let boost = (self.config.max_rate - prosody.rate) * busy;
+            prosody.rate += (boost * 4.0).round() / 4.0;
+        }
+        prosody
+    }
+
+    /// Whether anything is being spoken or waiting to be spoken. Nothing
+    /// counts while paused, as held audio doesn't finish.
+    pub fn has_pending_speech(&self) -> bool {
+        !self.paused
+            && (self.current.is_some() || self.engine.is_speaking() || !self.queue.is_empty())
+    }
+
+    // Log the utterance that just finished speaking
+    fn finish_speech(&mut self) {
+        let Some(speech) = self.current.take() else {
+            return;
+        };
+
+        let disposition = if let Some(error) = self.engine.take_error() {
+            // It was counted as spoken when it started
+            self.stats.spoken = self.stats.spoken.saturating_sub(1);
+            self.stats.failed += 1;
+            Disposition::Failed { error }
+        } else if speech.skipped {
+            Disposition::Skipped
+        } else {
+            Disposition::Spoken
+        };
+        let mut entry = Self::speech_entry(&speech, disposition);
+        entry.spoken_ms = Some(speech.started.elapsed().as_millis() as u64);
+        self.record(&entry);
+    }
+
+    fn speech_entry(speech: &Speech, disposition: Disposition) -> HistoryEntry {
+        let utterance = &speech.utterance;
+        let mut entry = utterance.history_entry(disposition);
+        entry.voice = Some(speech.voice.clone());
+        entry.started_at = Some(speech.started_at.to_rfc3339());
+        entry.queued_ms = Some(
+            speech
+                .started
+                .saturating_duration_since(utterance.queued_at)
+                .as_millis() as u64,
+        );
+        entry
+    }
+
+    fn record_filtered(&mut self, utterance: &Utterance, reason: FilterReason) {
+        let entry = utterance.history_entry(Disposition::Filtered {
+            reason: reason.to_string(),
+        });
+        self.record(&entry);
+    }
+
+    fn record(&mut self, entry: &HistoryEntry) {
+        if let Some(history) = &mut self.history {
+            history.append(entry);
+        }
+    }
+
+    /// Whether switching to `config` needs a new TTS engine
+    pub fn needs_new_engine(&self, config: &Config) -> bool {
+        self.config
+            .changed_keys(config)
+            .iter()
+            .any(|key| ENGINE_SETTINGS.contains(&key.as_str()))
+    }
+
+    /// Switch to a reloaded config, with the engine built for it if
+    /// `needs_new_engine`. Filters and templates apply to queued messages at
+    /// once, the engine from the next message. Returns a summary of the changes.
+    pub fn reload_config(
+        &mut self,
+        config: Config,
+        engine: Option<Box<dyn TextToSpeech>>,
+    ) -> String {
+        let changed = self.config.changed_keys(&config);
+        if changed.is_empty() {
+            return "no changes".to_string();
+        }
+
+        if let Some(engine) = engine {
+            self.pending_engine = Some(engine);
+        }
+        self.config = config;
+        self.refilter_queue();
+
+        let mut summary = format!("changed {}", changed.join(", "));
+        let restart: Vec<&str> = changed
+            .iter()
+            .map(String::as_str)
+            .filter(|key| STARTUP_SETTINGS.contains(key))
+            .collect();
+        if !restart.is_empty() {
+            summary.push_str(&format!(
+                " ({} take effect after a restart)",
+                restart.join(", ")
+            ));
+        }
+        summary
+    }
+
+    // Render queued chat messages again with the current templates and drop
+    // the ones the current filters reject
+    fn refilter_queue(&mut self) {
+        for mut utterance in std::mem::take(&mut self.queue) {
+            let Some(message) = &utterance.message else {
+                self.queue.push_back(utterance);
+                continue;
+            };
+
+            let speech = Markup::render(self.config.message_template_for(&message.source), message);
+            let verdict = self
+                .config
+                .filters_for(&message.source)
+                .try_for_each(|filter| filter.check(message));
+            utterance.text = speech.to_plain();
+            utterance.speech = speech;
+
+            match verdict {
+                Ok(()) => self.queue.push_back(utterance),
+                Err(reason) => {
+                    self.stats.filtered += 1;
+                    self.record_filtered(&utterance, reason);
+                }
+            }
+        }
+    }
+
+    pub fn execute(&mut self, command: Command) -> Result<String> {
+        let response = match command {
+            Command::Skip => {
+                if self.engine.is_speaking() {
+                    self.engine.stop();
+                    self.stats.skipped += 1;
+                    if let Some(speech) = &mut self.current {
+                        speech.skipped = true;
+                    }
+                    "Skipped current message".to_string()
+                } else {
+                    "Nothing is being spoken".to_string()
+                }
+            }
+            Command::Pause => {
+                self.paused = true;
+                self.engine.pause();
+                "Paused".to_string()
+            }
+            Command::Resume => {
+                self.paused = false;
+                self.engine.resume();
+                "Resumed".to_string()
+            }
+            Command::Say(text) => {
+                self.queue
+                    .push_front(Utterance::new(None, Markup::plain(&text)));
+                "Queued to be read next".to_string()
+            }
+            Command::Mute(author) => {
+                let (removed, kept): (VecDeque<_>, VecDeque<_>) = self
+                    .queue
+                    .drain(..)
+                    .partition(|u| u.author().is_some_and(|a| author_eq(a, &author)));
+                self.queue = kept;
+                for utterance in &removed {
+                    self.record_filtered(utterance, FilterReason::MutedAuthor);
+                }
+                let removed = removed.len();
+                self.stats.muted += removed as u64;
+                self.muted.insert(author.to_lowercase());
+                format!("Muted {} ({} queued messages removed)", author, removed)
+            }
+            Command::Unmute(author) => {
+                if self.muted.remove(&author.to_lowercase()) {
+                    format!("Unmuted {}", author)
+                } else {
+                    format!("{} was not muted", author)
+                }
+            }
+            Command::Voice(name) => {
+                let mut config = self.config.clone();
+                match config.tts_engine {
+                    TtsEngineType::Windows => config.windows_voice = name.clone(),
+                    TtsEngineType::OpenAI => config.openai_voice = name.clone(),
+                }
+                let engine = tts::create_tts_engine(&config)?;
+                self.config = config;
+                self.pending_engine = Some(engine);
+                format!("Voice set to {} from the next message", name)
+            }
+            Command::Queue => {
+                if self.queue.is_empty() {
+                    "Queue is empty".to_string()
+                } else {
+                    self.queue
+                        .iter()
+                        .enumerate()
+                        .map(|(i, u)| format!("{:>3}. {}", i + 1, u.text))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+            Command::Stats => self.format_stats(),
+            Command::Help => HELP.to_string(),
+        };
+
+        Ok(response)
+    }
+
+    fn is_muted(&self, author: &str) -> bool {
+        self.muted.contains(&author.to_lowercase())
+    }
+
+    fn voice(&self) -> &str {
+        match self.config.tts_engine {
+            TtsEngineType::Windows => &self.config.windows_voice,
+            TtsEngineType::OpenAI => &self.config.openai_voice,
+        }
+    }
+
+    fn format_stats(&self) -> String {
+        let uptime = self.started_at.elapsed().as_secs();
+
+        let mut stats = format!(
+            "Uptime: {}h{:02}m{:02}s\n\
+             Engine: {:?} ({}, rate {:.2})\n\
+             State: {}\n\
+             Received: {}, spoken: {}, skipped: {}, muted: {}, filtered: {}, \
+             dropped: {}, failed: {}\n\
+             Queued: {}, muted authors: {}",
+            uptime / 3600,
+            uptime / 60 % 60,
+            uptime % 60,
+            self.config.tts_engine,
+            self.voice(),
+            self.prosody().rate,
+            if self.paused { "paused" } else { "running" },
+            self.stats.received,
+            self.stats.spoken,
+            self.stats.skipped,
+            self.stats.muted,
+            self.stats.filtered,
+            self.stats.dropped,
+            self.stats.failed,
+            self.queue.len(),
+            self.muted.len(),
+        );
+
+        if let Some(status) = self.engine.status() {
+            stats.push_str(&format!("\nTTS: {}", status));
+        }
+
+        if let Some(quota) = &self.quota {
+            stats.push_str(&format!("\nYouTube quota: {}", quota.summary()));
+        }
+
+        stats
+    }
+}
+
+fn author_eq(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SourceConfig;
+    use crate::history::{self, HistoryQuery};
+
+    // Finishes every message instantly, failing on demand
+    struct InstantEngine;
+
+    impl TextToSpeech for InstantEngine {
+        fn speak(&self, speech: &Markup, _prosody: Prosody) -> Result<()> {
+            if speech.to_plain().contains("fail") {
+                anyhow::bail!("synthesis failed");
+            }
+            Ok(())
+        }
+
+        fn is_speaking(&self) -> bool {
+            false
+        }
+
+        fn stop(&self) {}
+    }
+
+    // Never finishes speaking and remembers what it was asked to prefetch
+    #[derive(Default)]
+    struct PrefetchingEngine {
+        prefetched: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl TextToSpeech for std::sync::Arc<PrefetchingEngine> {
+        fn speak(&self, _speech: &Markup, _prosody: Prosody) -> Result<()> {
+            Ok(())
+        }
+
+        fn is_speaking(&self) -> bool {
+            true
+        }
+
+        fn stop(&self) {}
+
+        fn prefetch(&self, upcoming: &[String], _prosody: Prosody) {
+            *self.prefetched.lock().unwrap() = upcoming.to_vec();
+        }
+    }
+
+    fn message(id: &str, author: &str, text: &str) -> ChatMessage {
+        ChatMessage {
+            id: id.to_string(),
+            author: author.to_string(),
+            text: text.to_string(),
+            timestamp: format!("2024-01-01T00:00:{:02}+00:00", id.parse::<u32>().unwrap()),
+            source: String::new(),
+            roles: Vec::new(),
+            amount: None,
+        }
+    }
+
+    #[test]
+    fn records_every_disposition() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+
+        let mut config = Config {
+            max_queue_length: 2,
+            message_template: "{author}: {text}".to_string(),
+            ..Config::default()
+        };
+        config.filter.blocked_words = vec!["spam".to_string()];
+
+        let mut session = Session::new(config, Box::new(InstantEngine));
+        session.set_history(History::open(&path).unwrap());
+
+        session.enqueue_message(message("1", "a", "buy spam"));
+        session.enqueue_message(message("2", "b", "oldest"));
+        session.enqueue_message(message("3", "c", "please fail"));
+        session.enqueue_message(message("4", "d", "hello"));
+        while session.has_pending_speech() {
+            session.tick().unwrap();
+        }
+
+        let entries = history::query(&path, &HistoryQuery::default()).unwrap();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.text.as_str(), e.disposition.name()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a: buy spam", "filtered"),
+                ("b: oldest", "dropped"),
+                ("c: please fail", "failed"),
+                ("d: hello", "spoken"),
+            ]
+        );
+        assert!(entries[3].voice.is_some() && entries[3].spoken_ms.is_some());
+
+        let query = HistoryQuery {
+            keyword: Some("HELLO".to_string()),
+            ..HistoryQuery::default()
+        };
+        assert_eq!(history::query(&path, &query).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn prefetches_upcoming_messages() {
+        let engine = std::sync::Arc::new(PrefetchingEngine::default());
+        let config = Config {
+            prefetch_count: 2,
+            max_queue_length: 3,
+            message_template: "{text}".to_string(),
+            ..Config::default()
+        };
+        let mut session = Session::new(config, Box::new(engine.clone()));
+
+        session.enqueue_message(message("1", "a", "first"));
+        session.enqueue_message(message("2", "b", "second"));
+        session.enqueue_message(message("3", "c", "third"));
+        session.tick().unwrap();
+        assert_eq!(*engine.prefetched.lock().unwrap(), vec!["first", "second"]);
+
+        // A message dropped from the queue is no longer prefetched
+        session.enqueue_message(message("4", "d", "fourth"));
+        session.tick().unwrap();
+        assert_eq!(*engine.prefetched.lock().unwrap(), vec!["second", "third"]);
+    }
+
+    #[test]
+    fn reload_applies_filters_to_the_queue() {
+        let config = Config {
+            api_key: "old".to_string(),
+            message_template: "{text}".to_string(),
+            ..Config::default()
+        };
+        let mut session = Session::new(config.clone(), Box::new(InstantEngine));
+        session.enqueue_message(message("1", "a", "hello"));
+        session.enqueue_message(message("2", "b", "buy spam"));
+        session
+            .queue
+            .push_front(Utterance::new(None, Markup::plain("announcement")));
+        assert_eq!(session.reload_config(config.clone(), None), "no changes");
+
+        let mut reloaded = Config {
+            api_key: "new".to_string(),
+            message_template: "{author}: {text}".to_string(),
+            rate: 1.5,
+            ..config
+        };
+        reloaded.filter.blocked_words = vec!["spam".to_string()];
+        assert!(!session.needs_new_engine(&reloaded));
+        assert_eq!(
+            session.reload_config(reloaded, None),
+            "changed api_key, filter, message_template, rate \
+             (api_key take effect after a restart)"
+        );
+
+        let queued: Vec<&str> = session.queue.iter().map(|u| u.text.as_str()).collect();
+        assert_eq!(queued, ["announcement", "a: hello"]);
+        assert_eq!(session.stats.filtered, 1);
+        assert_eq!(session.prosody().rate, 1.5);
+        assert!(session.pending_engine.is_none());
+    }
+
+    #[test]
+    fn adaptive_rate_follows_queue_length() {
+        let config = Config {
+            rate: 1.0,
+            adaptive_rate: true,
+            max_rate: 2.0,
+            ..Config::default()
+        };
+        let mut session = Session::new(config, Box::new(InstantEngine));
+        assert_eq!(session.prosody().rate, 1.0);
+
+        for i in 0..5 {
+            session.enqueue_message(message(&i.to_string(), "a", "hello"));
+        }
+        assert_eq!(session.prosody().rate, 1.5);
+
+        for i in 5..20 {
+            session.enqueue_message(message(&i.to_string(), "a", "hello"));
+        }
+        assert!(session.queue.iter().all(|u| u.published_at.is_some()));
+        assert_eq!(session.prosody().rate, 2.0);
+    }
+
+    fn message_from(source: &str, id: &str, author: &str, text: &str) -> ChatMessage {
+        ChatMessage {
+            source: source.to_string(),
+            ..message(id, author, text)
+        }
+    }
+
+    fn queued_texts(session: &Session) -> Vec<&str> {
+        session.queue.iter().map(|u| u.text.as_str()).collect()
+    }
+
+    #[test]
+    fn interleaves_sources_in_publish_order() {
+        let config = Config {
+            message_template: "{text}".to_string(),
+            ..Config::default()
+        };
+        let mut session = Session::new(config, Box::new(InstantEngine));
+
+        // One batch from each stream, the second one published in between
+        session.enqueue_message(message_from("youtube", "1", "a", "one"));
+        session.enqueue_message(message_from("youtube", "4", "a", "four"));
+        session.enqueue_message(message_from("twitch", "2", "b", "two"));
+        session.enqueue_message(message_from("twitch", "5", "b", "five"));
+        session.enqueue_message(message_from("twitch", "3", "b", "three"));
+        // Same publish time as a queued message, so it goes after it
+        session.enqueue_message(message_from("youtube", "3", "a", "three again"));
+
+        assert_eq!(
+            queued_texts(&session),
+            vec!["one", "two", "three", "three again", "four", "five"]
+        );
+    }
+
+    #[test]
+    fn applies_per_source_templates_and_filters() {
+        let mut config = Config {
+            message_template: "{author} says {text}".to_string(),
+            ..Config::default()
+        };
+        config.filter.blocked_words = vec!["spam".to_string()];
+        let mut twitch = SourceConfig {
+            message_template: Some("{text}".to_string()),
+            ..SourceConfig::default()
+        };
+        twitch.filter.max_length = Some(10);
+        config.sources.insert("twitch".to_string(), twitch);
+        let mut session = Session::new(config, Box::new(InstantEngine));
+
+        session.enqueue_message(message_from("twitch", "1", "a", "short"));
+        session.enqueue_message(message_from("twitch", "2", "a", "far too long for twitch"));
+        session.enqueue_message(message_from("twitch", "3", "a", "spam"));
+        session.enqueue_message(message_from("youtube", "4", "b", "long is fine here"));
+        session.enqueue_message(message_from("youtube", "5", "b", "spam"));
+
+        assert_eq!(
+            queued_texts(&session),
+            vec!["short", "b says long is fine here"]
+        );
+        assert_eq!(session.stats.filtered, 3);
+    }
+}
@@ -0,0 +1,329 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines, Stdin},
+    net::TcpStream,
+    time::sleep,
+};
+use tokio_tungstenite::{
+    connect_async, tungstenite::Message as WsMessage, MaybeTlsStream, WebSocketStream,
+};
+
+use crate::backoff::Backoff;
+use crate::error::AppError;
+use crate::recorder::{Entry, Record};
+
+// Consecutive failed connection attempts before a network source gives up
+pub(crate) const MAX_RECONNECTS: u32 = 10;
+
+/// A chat message from any supported platform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub id: String,
+    pub author: String,
+    pub text: String,
+    pub timestamp: String,
+    // Label of the stream the message came from, when watching several
+    #[serde(default)]
+    pub source: String,
+    // Author roles: owner, moderator, member, verified, vip and platform-specific badges
+    #[serde(default)]
+    pub roles: Vec<String>,
+    // Paid message amount, e.g. "¥500" for a Super Chat or "100 bits" on Twitch
+    #[serde(default)]
+    pub amount: Option<String>,
+}
+
+/// Anything that produces chat messages for the bot to read
+#[async_trait]
+pub trait ChatSource: Send {
+    /// Wait for the next message. Returns `None` once the chat has ended.
+    async fn next_message(&mut self) -> Result<Option<ChatMessage>>;
+}
+
+// Messages from generic sources only need an author and text
+#[derive(Debug, Deserialize)]
+struct IncomingMessage {
+    id: Option<String>,
+    author: String,
+    text: String,
+    timestamp: Option<String>,
+    #[serde(default)]
+    source: String,
+    #[serde(default)]
+    roles: Vec<String>,
+    amount: Option<String>,
+}
+
+/// Parse one JSON chat message, filling in a missing ID or timestamp
+pub fn parse_message(json: &str) -> Result<ChatMessage> {
+    let incoming: IncomingMessage = serde_json::from_str(json).map_err(AppError::Json)?;
+    let timestamp = incoming
+        .timestamp
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+
+    Ok(ChatMessage {
+        id: incoming
+            .id
+            .unwrap_or_else(|| format!("{:016x}", fastrand::u64(..))),
+        author: incoming.author,
+        text: incoming.text,
+        timestamp,
+        source: incoming.source,
+        roles: incoming.roles,
+        amount: incoming.amount,
+    })
+}
+
+// Read lines until `parse` returns a value. Lines it returns `None` for are
+// skipped; malformed lines are logged and skipped.
+async fn next_json_line<R, T>(
+    lines: &mut Lines<R>,
+    name: &str,
+    parse: impl Fn(&str) -> Result<Option<T>>,
+) -> Result<Option<T>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    while let Some(line) = lines.next_line().await.map_err(AppError::IO)? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse(&line) {
+            Ok(Some(value)) => return Ok(Some(value)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Skipping malformed message from {}: {}", name, e),
+        }
+    }
+    Ok(None)
+}
+
+// A replay line is either a recorder entry, paced by when it was received, or
+// a bare message, paced by its timestamp. Other recorder entries are skipped.
+fn parse_replay_line(line: &str) -> Result<Option<(ChatMessage, String)>> {
+    if let Ok(record) = serde_json::from_str::<Record>(line) {
+        return Ok(match record.entry {
+            Entry::Message { message } => Some((message, record.received_at)),
+            _ => None,
+        });
+    }
+
+    let message = parse_message(line)?;
+    let time = message.timestamp.clone();
+    Ok(Some((message, time)))
+}
+
+/// Chat messages written to stdin as JSON lines
+pub struct StdinSource {
+    lines: Lines<BufReader<Stdin>>,
+}
+
+impl StdinSource {
+    pub fn new() -> Self {
+        Self {
+            lines: BufReader::new(tokio::io::stdin()).lines(),
+        }
+    }
+}
+
+impl Default for StdinSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ChatSource for StdinSource {
+    async fn next_message(&mut self) -> Result<Option<ChatMessage>> {
+        next_json_line(&mut self.lines, "stdin", |line| {
+            parse_message(line).map(Some)
+        })
+        .await
+    }
+}
+
+/// Replays a chat recording or JSON-lines chat log, keeping the original gaps
+/// between messages
+pub struct ReplaySource {
+    path: String,
+    lines: Lines<BufReader<File>>,
+    // 2.0 plays twice as fast; 0 or less plays without pauses
+    speed: f64,
+    previous: Option<DateTime<FixedOffset>>,
+}
+
+impl ReplaySource {
+    pub async fn open(path: &str, speed: f64) -> Result<Self> {
+        let file = File::open(path).await.map_err(AppError::IO)?;
+        Ok(Self {
+            path: path.to_string(),
+            lines: BufReader::new(file).lines(),
+            speed,
+            previous: None,
+        })
+    }
+}
+
+#[async_trait]
+impl ChatSource for ReplaySource {
+    async fn next_message(&mut self) -> Result<Option<ChatMessage>> {
+        let Some((message, time)) =
+            next_json_line(&mut self.lines, &self.path, parse_replay_line).await?
+        else {
+            return Ok(None);
+        };
+
+        if let Ok(time) = DateTime::parse_from_rfc3339(&time) {
+            if let Some(previous) = self.previous {
+                let gap = (time - previous).to_std().unwrap_or_default();
+                if self.speed > 0.0 && !gap.is_zero() {
+                    sleep(gap.div_f64(self.speed)).await;
+                }
+            }
+            self.previous = Some(time);
+        }
+
+        Ok(Some(message))
+    }
+}
+
+/// Chat messages received as JSON from a WebSocket server, reconnecting when
+/// the connection drops
+pub struct WebSocketSource {
+    url: String,
+    stream: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+    backoff: Backoff,
+}
+
+impl WebSocketSource {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            stream: None,
+            backoff: Backoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+        }
+    }
+
+    async fn wait_before_reconnect(&mut self, err: anyhow::Error) -> Result<()> {
+        self.stream = None;
+
+        if self.backoff.attempts() >= MAX_RECONNECTS {
+            tracing::error!("Giving up on WebSocket {}", self.url);
+            return Err(err);
+        }
+
+        let delay = self.backoff.next_delay();
+        tracing::warn!(
+            "WebSocket {} disconnected: {}. Reconnecting in {:.1}s",
+            self.url,
+            err,
+            delay.as_secs_f64()
+        );
+        sleep(delay).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChatSource for WebSocketSource {
+    async fn next_message(&mut self) -> Result<Option<ChatMessage>> {
+        loop {
+            if self.stream.is_none() {
+                match connect_async(self.url.as_str()).await {
+                    Ok((stream, _)) => {
+                        tracing::info!("Connected to WebSocket {}", self.url);
+                        self.stream = Some(stream);
+                        self.backoff.reset();
+                    }
+                    Err(e) => {
+                        self.wait_before_reconnect(e.into()).await?;
+                        continue;
+                    }
+                }
+            }
+
+            let stream = self.stream.as_mut().expect("connected above");
+            let text = match stream.next().await {
+                Some(Ok(WsMessage::Text(text))) => text,
+                Some(Ok(WsMessage::Binary(data))) => match String::from_utf8(data) {
+                    Ok(text) => text,
+                    Err(_) => continue,
+                },
+                Some(Ok(WsMessage::Close(_))) | None => {
+                    self.wait_before_reconnect(anyhow::anyhow!("connection closed"))
+                        .await?;
+                    continue;
+                }
+                // Pings are answered by the library
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    self.wait_before_reconnect(e.into()).await?;
+                    continue;
+                }
+            };
+
+            match parse_message(&text) {
+                Ok(message) => return Ok(Some(message)),
+                Err(e) => tracing::warn!("Skipping malformed message from {}: {}", self.url, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::time::Instant;
+
+    #[test]
+    fn parses_minimal_messages() {
+        let message = parse_message(r#"{"author": "viewer", "text": "hello"}"#).unwrap();
+        assert_eq!(message.author, "viewer");
+        assert_eq!(message.text, "hello");
+        assert!(!message.id.is_empty());
+        assert!(DateTime::parse_from_rfc3339(&message.timestamp).is_ok());
+
+        assert!(parse_message(r#"{"text": "no author"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn replays_with_scaled_timing() {
+        let mut log = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            log,
+            r#"{{"id":"1","author":"a","text":"first","timestamp":"2024-01-01T00:00:00+00:00"}}"#
+        )
+        .unwrap();
+        writeln!(log, "not json").unwrap();
+        writeln!(
+            log,
+            r#"{{"received_at":"2024-01-01T00:00:01+00:00","kind":"event","source":"x","event":"connected"}}"#
+        )
+        .unwrap();
+        writeln!(
+            log,
+            r#"{{"id":"2","author":"b","text":"second","timestamp":"2024-01-01T00:00:02+00:00"}}"#
+        )
+        .unwrap();
+
+        let path = log.path().to_str().unwrap();
+        let mut replay = ReplaySource::open(path, 10.0).await.unwrap();
+        let started = Instant::now();
+
+        assert_eq!(replay.next_message().await.unwrap().unwrap().id, "1");
+        assert_eq!(replay.next_message().await.unwrap().unwrap().id, "2");
+        assert!(replay.next_message().await.unwrap().is_none());
+
+        // Two seconds of chat at 10x speed
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+}
@@ -0,0 +1,211 @@
+/// What the user asked the bot to watch: a video, or a channel whose live
+/// stream should be looked up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Video(String),
+    ChannelId(String),
+    /// Channel handle, without the leading `@`
+    Handle(String),
+    /// Legacy channel username
+    Username(String),
+}
+
+impl Target {
+    /// Parse a `--video-id` argument. Anything that is not a recognizable
+    /// URL, handle or channel ID is taken as a video ID.
+    pub fn from_video_arg(input: &str) -> Self {
+        let input = input.trim();
+        parse(input).unwrap_or_else(|| Target::Video(input.to_string()))
+    }
+
+    /// Parse a `--channel-id` argument. Anything that is not a recognizable
+    /// URL, handle or channel ID is taken as a legacy username.
+    pub fn from_channel_arg(input: &str) -> Self {
+        let input = input.trim();
+        parse(input).unwrap_or_else(|| Target::Username(input.to_string()))
+    }
+}
+
+fn is_channel_id(s: &str) -> bool {
+    s.len() == 24 && s.starts_with("UC") && s.chars().all(is_id_char)
+}
+
+fn is_video_id(s: &str) -> bool {
+    s.len() == 11 && s.chars().all(is_id_char)
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+// Recognize URLs, `@handle` and `UC…` channel IDs. Returns `None` for bare words,
+// whose meaning depends on which argument they were given to.
+fn parse(input: &str) -> Option<Target> {
+    if let Some(handle) = input.strip_prefix('@') {
+        return non_empty(handle).map(Target::Handle);
+    }
+
+    if is_channel_id(input) {
+        return Some(Target::ChannelId(input.to_string()));
+    }
+
+    parse_url(input)
+}
+
+fn parse_url(input: &str) -> Option<Target> {
+    let rest = input
+        .strip_prefix("https://")
+        .or_else(|| input.strip_prefix("http://"))
+        .unwrap_or(input);
+
+    let rest = rest.split('#').next().unwrap_or("");
+    let (host, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    let host = host.to_lowercase();
+    let host = host
+        .strip_prefix("www.")
+        .or_else(|| host.strip_prefix("m."))
+        .unwrap_or(&host);
+
+    let (path, query) = match rest.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (rest, ""),
+    };
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match host {
+        "youtu.be" => segments
+            .first()
+            .filter(|id| is_video_id(id))
+            .map(|id| Target::Video(id.to_string())),
+        "youtube.com" => parse_youtube_path(&segments, query),
+        _ => None,
+    }
+}
+
+fn parse_youtube_path(segments: &[&str], query: &str) -> Option<Target> {
+    match segments {
+        ["watch", ..] => query_param(query, "v")
+            .filter(|id| is_video_id(id))
+            .map(Target::Video),
+        ["live" | "shorts" | "embed" | "v", id, ..] if is_video_id(id) => {
+            Some(Target::Video(id.to_string()))
+        }
+        ["channel", id, ..] if is_channel_id(id) => Some(Target::ChannelId(id.to_string())),
+        ["user", name, ..] => non_empty(name).map(Target::Username),
+        // Custom /c/ URLs can't be looked up directly; most match the channel's handle
+        ["c", name, ..] => non_empty(name).map(Target::Handle),
+        [handle, ..] if handle.starts_with('@') => non_empty(&handle[1..]).map(Target::Handle),
+        _ => None,
+    }
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIDEO: &str = "dQw4w9WgXcQ";
+    const CHANNEL: &str = "UCuAXFkgsw1L7xaCfnd5JJOw";
+
+    fn video() -> Target {
+        Target::Video(VIDEO.to_string())
+    }
+
+    #[test]
+    fn bare_arguments() {
+        assert_eq!(Target::from_video_arg(VIDEO), video());
+        assert_eq!(
+            Target::from_channel_arg("somebody"),
+            Target::Username("somebody".to_string())
+        );
+        assert_eq!(
+            Target::from_channel_arg(CHANNEL),
+            Target::ChannelId(CHANNEL.to_string())
+        );
+        assert_eq!(
+            Target::from_channel_arg("@streamer"),
+            Target::Handle("streamer".to_string())
+        );
+        assert_eq!(
+            Target::from_video_arg("  @streamer "),
+            Target::Handle("streamer".to_string())
+        );
+    }
+
+    #[test]
+    fn watch_urls() {
+        for url in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtube.com/watch?feature=share&v=dQw4w9WgXcQ&t=42",
+            "http://m.youtube.com/watch?v=dQw4w9WgXcQ#comments",
+            "www.youtube.com/watch?v=dQw4w9WgXcQ",
+        ] {
+            assert_eq!(Target::from_channel_arg(url), video(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn short_and_live_urls() {
+        for url in [
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://youtu.be/dQw4w9WgXcQ?si=abcdef",
+            "https://www.youtube.com/live/dQw4w9WgXcQ?feature=shared",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(Target::from_video_arg(url), video(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn channel_urls() {
+        let channel = Target::ChannelId(CHANNEL.to_string());
+        assert_eq!(
+            Target::from_video_arg(&format!("https://www.youtube.com/channel/{}", CHANNEL)),
+            channel
+        );
+        assert_eq!(
+            Target::from_video_arg(&format!("https://youtube.com/channel/{}/live", CHANNEL)),
+            channel
+        );
+        assert_eq!(
+            Target::from_video_arg("https://www.youtube.com/user/legacyname/videos"),
+            Target::Username("legacyname".to_string())
+        );
+    }
+
+    #[test]
+    fn handle_urls() {
+        let handle = Target::Handle("streamer".to_string());
+        for url in [
+            "https://www.youtube.com/@streamer",
+            "https://www.youtube.com/@streamer/live",
+            "youtube.com/@streamer/streams",
+            "https://www.youtube.com/c/streamer",
+        ] {
+            assert_eq!(Target::from_video_arg(url), handle, "{}", url);
+        }
+    }
+
+    #[test]
+    fn unrecognized_urls_fall_back() {
+        assert_eq!(
+            Target::from_video_arg("https://example.com/watch?v=dQw4w9WgXcQ"),
+            Target::Video("https://example.com/watch?v=dQw4w9WgXcQ".to_string())
+        );
+        assert_eq!(
+            Target::from_channel_arg("https://www.youtube.com/watch?v=short"),
+            Target::Username("https://www.youtube.com/watch?v=short".to_string())
+        );
+    }
+}
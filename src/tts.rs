@@ -1,30 +1,137 @@
 use std::{
-    io::Cursor,
-    path::PathBuf,
+    collections::HashMap,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        mpsc, Arc, Mutex,
     },
     time::Duration,
 };
 
 use anyhow::Result;
 use reqwest;
-use rodio;
 use serde_json;
-use tempfile;
-use tokio::{fs::File, io::AsyncWriteExt};
+use tokio::{sync::Semaphore, task::JoinHandle};
 
+use crate::backoff::Backoff;
+use crate::cache::AudioCache;
 use crate::config::TtsEngine as TtsEngineType;
 use crate::error::AppError;
+use crate::fallback::FallbackEngine;
+use crate::markup::Markup;
+use crate::playback::{self, PcmStream, Player};
+use crate::usage::{RateLimiter, TtsUsage};
+
+// How often playback checks whether it has been asked to stop
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Wait for the OpenAI API to respond unless configured otherwise
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Delays between retries of rate-limited or failed OpenAI requests. A server
+// asking to wait longer than the maximum is not retried.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(20);
+
+// Prefetch requests allowed to run at once unless configured otherwise
+const DEFAULT_PREFETCH_CONCURRENCY: usize = 2;
+
+// OpenAI `pcm` audio is 24 kHz, 16-bit signed little-endian mono
+const PCM_SAMPLE_RATE: u32 = 24_000;
+
+// Audio buffered before playback starts (200 ms), so a slow download doesn't stutter
+const PCM_PREBUFFER_BYTES: usize = PCM_SAMPLE_RATE as usize * 2 / 5;
+
+/// How fast, high and loud speech is, as multiples of each engine's normal setting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prosody {
+    pub rate: f32,
+    pub pitch: f32,
+    pub volume: f32,
+}
+
+impl Prosody {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        Self {
+            rate: config.rate,
+            pitch: config.pitch,
+            volume: config.volume,
+        }
+    }
+}
+
+impl Default for Prosody {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            pitch: 1.0,
+            volume: 1.0,
+        }
+    }
+}
 
 pub trait TextToSpeech: Send + Sync {
-    fn speak(&self, text: &str) -> Result<()>;
+    // Engines that accept SSML render `speech` to SSML, others to plain text
+    fn speak(&self, speech: &Markup, prosody: Prosody) -> Result<()>;
+
+    fn is_speaking(&self) -> bool;
+
+    // Interrupt the message that is currently being spoken, if any
+    fn stop(&self);
+
+    // Hold and continue the audio that is playing. Engines that can't let the
+    // current message finish.
+    fn pause(&self) {}
+
+    fn resume(&self) {}
+
+    // Name of the voice speaking the current message, if the engine knows it
+    fn current_voice(&self) -> Option<String> {
+        None
+    }
+
+    // Synthesize the texts expected to be spoken next ahead of time, and drop
+    // prefetched audio for any text no longer listed. Engines that synthesize
+    // quickly don't need to.
+    fn prefetch(&self, _upcoming: &[String], _prosody: Prosody) {}
+
+    // Why the last message failed before any of it was heard. Engines that
+    // fail in the background report it here once they stop speaking, so the
+    // message can be retried with another engine.
+    fn take_error(&self) -> Option<String> {
+        None
+    }
+
+    // Engine health for the stats command, if there is anything to report
+    fn status(&self) -> Option<String> {
+        None
+    }
 }
 
-// Factory function to create the appropriate TTS engine
+// Factory function to create the appropriate TTS engine. Configured fallback
+// engines are chained behind it.
 pub fn create_tts_engine(config: &crate::config::Config) -> Result<Box<dyn TextToSpeech>> {
-    match config.tts_engine {
+    if config.fallback_engines.is_empty() {
+        return create_engine(&config.tts_engine, config);
+    }
+
+    let mut chain = FallbackEngine::new();
+    for engine_type in std::iter::once(&config.tts_engine).chain(&config.fallback_engines) {
+        match create_engine(engine_type, config) {
+            Ok(engine) => chain.push(format!("{:?}", engine_type), engine),
+            Err(e) => tracing::warn!("Skipping {:?} TTS engine: {}", engine_type, e),
+        }
+    }
+    if chain.is_empty() {
+        return Err(AppError::Config("No TTS engine could be created".to_string()).into());
+    }
+    Ok(Box::new(chain))
+}
+
+fn create_engine(
+    engine_type: &TtsEngineType,
+    config: &crate::config::Config,
+) -> Result<Box<dyn TextToSpeech>> {
+    match engine_type {
         TtsEngineType::Windows => {
             let mut engine = WindowsTtsEngine::new()?;
             let voice_name = if !config.windows_voice.is_empty() {
@@ -37,15 +144,31 @@ pub fn create_tts_engine(config: &crate::config::Config) -> Result<Box<dyn TextT
                 tracing::warn!("Failed to set Windows voice '{}': {}", voice_name, e);
                 tracing::info!("Using default Windows voice instead");
             }
+            engine.set_author_prosody(Prosody {
+                rate: config.author_rate,
+                pitch: config.author_pitch,
+                volume: 1.0,
+            });
             Ok(Box::new(engine))
         }
         TtsEngineType::OpenAI => {
             if let Some(api_key) = &config.openai_api_key {
-                Ok(Box::new(OpenAITtsEngine::new(
+                let mut engine = OpenAITtsEngine::new(
                     api_key.clone(),
                     config.openai_model.clone(),
                     config.openai_voice.clone(),
-                )?))
+                )?;
+                if let Some(cache) = config.audio_cache() {
+                    engine.set_cache(cache);
+                }
+                engine.set_prefetch_concurrency(config.prefetch_concurrency);
+                engine.set_timeout(Duration::from_secs(config.tts_timeout_secs));
+                engine.set_limits(config.openai_requests_per_minute, config.openai_max_retries);
+                engine.set_pricing(
+                    config.openai_pricing.get(&config.openai_model).copied(),
+                    config.openai_budget_usd,
+                );
+                Ok(Box::new(engine))
             } else {
                 Err(AppError::Config(
                     "OpenAI API key is required for OpenAI TTS engine".to_string(),
@@ -56,10 +179,31 @@ pub fn create_tts_engine(config: &crate::config::Config) -> Result<Box<dyn TextT
     }
 }
 
+/// Display names of the installed Windows voices
+pub fn windows_voice_names() -> Result<Vec<String>> {
+    use windows::Media::SpeechSynthesis::SpeechSynthesizer;
+
+    let voices = SpeechSynthesizer::AllVoices()
+        .map_err(|e| AppError::Windows(format!("Failed to get voices: {}", e)))?;
+    voices
+        .into_iter()
+        .map(|voice| {
+            voice
+                .DisplayName()
+                .map(|name| name.to_string())
+                .map_err(|e| AppError::Windows(format!("Failed to get voice name: {}", e)).into())
+        })
+        .collect()
+}
+
 // Windows TTS implementation
 pub struct WindowsTtsEngine {
     synthesizer: windows::Media::SpeechSynthesis::SpeechSynthesizer,
+    // How author names are read relative to the rest of the message
+    author_prosody: Prosody,
     is_speaking: Arc<AtomicBool>,
+    stop_requested: Arc<AtomicBool>,
+    last_error: Arc<Mutex<Option<String>>>,
 }
 
 impl WindowsTtsEngine {
@@ -71,10 +215,27 @@ impl WindowsTtsEngine {
 
         Ok(Self {
             synthesizer,
+            author_prosody: Prosody::default(),
             is_speaking: Arc::new(AtomicBool::new(false)),
+            stop_requested: Arc::new(AtomicBool::new(false)),
+            last_error: Arc::new(Mutex::new(None)),
         })
     }
 
+    pub fn set_author_prosody(&mut self, prosody: Prosody) {
+        self.author_prosody = prosody;
+    }
+
+    // Language of the selected voice, e.g. "ja-JP", needed by SSML
+    fn language(&self) -> Result<String> {
+        let language = self
+            .synthesizer
+            .Voice()
+            .and_then(|voice| voice.Language())
+            .map_err(|e| AppError::Windows(format!("Failed to get voice language: {}", e)))?;
+        Ok(language.to_string())
+    }
+
     pub fn set_voice(&mut self, voice_name: &str) -> Result<()> {
         use windows::Media::SpeechSynthesis::SpeechSynthesizer;
 
@@ -111,23 +272,42 @@ impl WindowsTtsEngine {
 }
 
 impl TextToSpeech for WindowsTtsEngine {
-    fn speak(&self, text: &str) -> Result<()> {
+    fn speak(&self, speech: &Markup, prosody: Prosody) -> Result<()> {
         use windows::core::HSTRING;
 
+        let text = speech.to_plain();
         if self.is_speaking.load(Ordering::SeqCst) {
             tracing::debug!("Already speaking, skipping text: {}", text);
             return Ok(());
         }
 
+        // Clamped to the ranges the synthesizer accepts
+        let options = self
+            .synthesizer
+            .Options()
+            .map_err(|e| AppError::Windows(format!("Failed to get voice options: {}", e)))?;
+        options
+            .SetSpeakingRate(prosody.rate.clamp(0.5, 6.0) as f64)
+            .and_then(|()| options.SetAudioPitch(prosody.pitch.clamp(0.0, 2.0) as f64))
+            .and_then(|()| options.SetAudioVolume(prosody.volume.clamp(0.0, 1.0) as f64))
+            .map_err(|e| AppError::Windows(format!("Failed to set voice options: {}", e)))?;
+
+        // Built before the speaking flag is set, so a failure here can't leave
+        // it stuck
+        let ssml = HSTRING::from(speech.to_ssml(&self.language()?, self.author_prosody));
+
         self.is_speaking.store(true, Ordering::SeqCst);
+        self.stop_requested.store(false, Ordering::SeqCst);
+        *self.last_error.lock().expect("error lock poisoned") = None;
         let is_speaking = self.is_speaking.clone();
+        let stop_requested = self.stop_requested.clone();
 
-        let text_hstring = HSTRING::from(text);
+        let last_error = self.last_error.clone();
         let synthesizer = self.synthesizer.clone();
 
         tokio::task::spawn_blocking(move || {
             let result = synthesizer
-                .SynthesizeTextToStreamAsync(&text_hstring)
+                .SynthesizeSsmlToStreamAsync(&ssml)
                 .and_then(|async_op| async_op.get())
                 .and_then(|stream| {
                     use std::thread;
@@ -176,157 +356,541 @@ impl TextToSpeech for WindowsTtsEngine {
                     })?;
 
                     // Estimate duration based on text length (rough approximation) with a minimum
-                    let estimated_duration_ms = (text_hstring.len() as u64 * 100).max(2000); // ~100ms per character with 2sec minimum
+                    let estimated_duration_ms = ((text.chars().count() as f32 * 100.0
+                        / prosody.rate.clamp(0.5, 6.0))
+                        as u64)
+                        .max(2000); // ~100ms per character with 2sec minimum
                     tracing::debug!(
                         "Playing audio, estimated duration: {}ms",
                         estimated_duration_ms
                     );
 
-                    // Sleep to allow playback to complete
-                    thread::sleep(Duration::from_millis(estimated_duration_ms));
+                    // Wait for playback to complete, unless asked to stop early
+                    let started = std::time::Instant::now();
+                    while started.elapsed() < Duration::from_millis(estimated_duration_ms) {
+                        if stop_requested.load(Ordering::SeqCst) {
+                            player.Pause()?;
+                            tracing::debug!("Audio playback stopped");
+                            return Ok(());
+                        }
+                        thread::sleep(STOP_POLL_INTERVAL);
+                    }
 
                     tracing::debug!("Audio playback completed");
                     Ok(())
                 });
 
-            is_speaking.store(false, Ordering::SeqCst);
-
             if let Err(e) = result {
                 tracing::error!("TTS error: {}", e);
+                *last_error.lock().expect("error lock poisoned") = Some(e.to_string());
             }
+
+            is_speaking.store(false, Ordering::SeqCst);
         });
 
         Ok(())
     }
+
+    fn is_speaking(&self) -> bool {
+        self.is_speaking.load(Ordering::SeqCst)
+    }
+
+    fn stop(&self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+    }
+
+    fn current_voice(&self) -> Option<String> {
+        let name = self
+            .synthesizer
+            .Voice()
+            .and_then(|voice| voice.DisplayName());
+        name.ok().map(|name| name.to_string())
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.last_error.lock().expect("error lock poisoned").take()
+    }
 }
 
-// OpenAI TTS implementation
-pub struct OpenAITtsEngine {
+// Everything needed to call the OpenAI speech API, shared by playback and prefetch tasks
+#[derive(Clone)]
+struct OpenAIRequester {
+    client: reqwest::Client,
     api_key: String,
     model: String,
     voice: String,
+    cache: Option<AudioCache>,
+    // Longest wait for the API to start responding
+    timeout: Duration,
+    limiter: RateLimiter,
+    max_retries: u32,
+    usage: TtsUsage,
+    // USD per million characters for the model, if known
+    price_per_million: Option<f64>,
+}
+
+impl OpenAIRequester {
+    fn cache_key(&self, text: &str, speed: f32) -> String {
+        let speed = format!("{:.2}", speed);
+        AudioCache::key(&["openai", &self.model, &self.voice, "pcm", &speed, text])
+    }
+
+    // Ask the OpenAI API to synthesize `text` as raw PCM. The audio is read
+    // from the returned response as it arrives.
+    async fn request(&self, text: &str, speed: f32) -> Result<reqwest::Response> {
+        let json = serde_json::json!({
+            "model": self.model,
+            "input": text,
+            "voice": self.voice,
+            "speed": speed,
+            "response_format": "pcm"
+        });
+
+        tracing::debug!("Sending TTS request to OpenAI API for text: {}", text);
+
+        if self.usage.over_budget() {
+            return Err(budget_error().into());
+        }
+
+        let mut backoff = Backoff::new(RETRY_BASE_DELAY, RETRY_MAX_DELAY);
+        loop {
+            self.limiter.acquire().await;
+
+            let request = self
+                .client
+                .post("https://api.openai.com/v1/audio/speech")
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .json(&json)
+                .send();
+            // Timeouts aren't retried, so a fallback engine can take over quickly
+            let response = tokio::time::timeout(self.timeout, request)
+                .await
+                .map_err(|_| {
+                    AppError::Tts(format!(
+                        "OpenAI did not respond within {}s",
+                        self.timeout.as_secs()
+                    ))
+                })??;
+
+            let status = response.status();
+            if status.is_success() {
+                self.usage.record(text, self.price_per_million);
+                return Ok(response);
+            }
+
+            let retry_after = retry_after(&response);
+            let error_text = response.text().await?;
+            let error = anyhow::anyhow!("OpenAI API error (HTTP {}): {}", status, error_text);
+
+            // Running out of credit is also a 429, but waiting won't fix it
+            let retryable = status.is_server_error()
+                || (status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    && !error_text.contains("insufficient_quota"));
+            if !retryable || backoff.attempts() >= self.max_retries {
+                return Err(error);
+            }
+
+            let delay = backoff.next_delay();
+            let delay = match retry_after {
+                Some(wait) if wait > RETRY_MAX_DELAY => return Err(error),
+                Some(wait) => wait,
+                None => delay,
+            };
+            tracing::warn!("{}. Retrying in {:.1}s", error, delay.as_secs_f64());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    // Complete audio for `text`, from the cache if possible
+    async fn synthesize(&self, text: &str, speed: f32) -> Result<Vec<u8>> {
+        let cache_key = self.cache_key(text, speed);
+        if let Some(audio) = self.cache.as_ref().and_then(|cache| cache.get(&cache_key)) {
+            return Ok(audio);
+        }
+
+        let audio = self.request(text, speed).await?.bytes().await?.to_vec();
+        if let Some(cache) = &self.cache {
+            cache.put(&cache_key, &audio);
+        }
+        Ok(audio)
+    }
+}
+
+// How long the server asked us to wait before retrying, in seconds or
+// OpenAI's milliseconds header
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|value| value.is_finite() && *value >= 0.0)
+    };
+
+    header("retry-after-ms")
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        .or_else(|| header("retry-after").map(Duration::from_secs_f64))
+}
+
+fn budget_error() -> AppError {
+    AppError::Tts("OpenAI spending budget reached".to_string())
+}
+
+// OpenAI has no pitch control, so pitch is changed by resampling during
+// playback. That also changes the tempo, which the requested speed makes up for.
+fn openai_speed(prosody: Prosody) -> f32 {
+    (prosody.rate / playback_pitch(prosody)).clamp(0.25, 4.0)
+}
+
+fn playback_pitch(prosody: Prosody) -> f32 {
+    prosody.pitch.clamp(0.5, 2.0)
+}
+
+// Take the complete 16-bit little-endian samples from the front of `pending`,
+// leaving a trailing odd byte for the next chunk
+fn take_pcm_samples(pending: &mut Vec<u8>) -> Vec<i16> {
+    let usable = pending.len() - pending.len() % 2;
+    let samples = pending[..usable]
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    pending.drain(..usable);
+    samples
+}
+
+// Feeds downloaded PCM bytes to the player, appending the stream once enough
+// is buffered to play smoothly
+struct PcmFeed {
+    player: Player,
+    prosody: Prosody,
+    samples: mpsc::Sender<Vec<i16>>,
+    stream: Option<PcmStream>,
+    pending: Vec<u8>,
+    buffered: usize,
+}
+
+impl PcmFeed {
+    fn new(
+        player: Player,
+        prosody: Prosody,
+        stop_requested: Arc<AtomicBool>,
+    ) -> (Self, Arc<AtomicBool>) {
+        let (samples, receiver) = mpsc::channel();
+        let stream = PcmStream::new(PCM_SAMPLE_RATE, receiver, stop_requested);
+        let finished = stream.finished();
+        let feed = Self {
+            player,
+            prosody,
+            samples,
+            stream: Some(stream),
+            pending: Vec::new(),
+            buffered: 0,
+        };
+        (feed, finished)
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<()> {
+        self.pending.extend_from_slice(bytes);
+        let samples = take_pcm_samples(&mut self.pending);
+        self.buffered += samples.len() * 2;
+        if !samples.is_empty() {
+            // The stream is gone only if playback was stopped
+            let _ = self.samples.send(samples);
+        }
+        if self.buffered >= PCM_PREBUFFER_BYTES {
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<()> {
+        match self.stream.take() {
+            Some(stream) => self.player.append(playback::adjust(
+                stream,
+                playback_pitch(self.prosody),
+                self.prosody.volume,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // Whether any audio has been handed to the player
+    fn started(&self) -> bool {
+        self.stream.is_none()
+    }
+
+    // Short messages may never fill the prebuffer
+    fn finish(mut self) -> Result<()> {
+        self.start()
+    }
+}
+
+fn log_player_error(result: Result<()>) {
+    if let Err(e) = result {
+        tracing::warn!("Audio playback control failed: {}", e);
+    }
+}
+
+// OpenAI TTS implementation
+pub struct OpenAITtsEngine {
+    requester: OpenAIRequester,
     is_speaking: Arc<AtomicBool>,
-    client: reqwest::Client,
-    temp_dir: PathBuf,
+    stop_requested: Arc<AtomicBool>,
+    last_error: Arc<Mutex<Option<String>>>,
+    // Audio being synthesized ahead of playback, keyed by cache key
+    prefetched: Mutex<HashMap<String, JoinHandle<Result<Vec<u8>>>>>,
+    // Limits concurrent prefetch requests
+    prefetch_permits: Arc<Semaphore>,
 }
 
 impl OpenAITtsEngine {
     pub fn new(api_key: String, model: String, voice: String) -> Result<Self> {
-        let temp_dir = tempfile::Builder::new()
-            .prefix("youtube-live-tts")
-            .tempdir()?
-            .into_path();
-
         Ok(Self {
-            api_key,
-            model,
-            voice,
+            requester: OpenAIRequester {
+                client: reqwest::Client::new(),
+                api_key,
+                model,
+                voice,
+                cache: None,
+                timeout: DEFAULT_REQUEST_TIMEOUT,
+                limiter: RateLimiter::per_minute(0),
+                max_retries: 0,
+                usage: TtsUsage::session(),
+                price_per_million: None,
+            },
             is_speaking: Arc::new(AtomicBool::new(false)),
-            client: reqwest::Client::new(),
-            temp_dir,
+            stop_requested: Arc::new(AtomicBool::new(false)),
+            last_error: Arc::new(Mutex::new(None)),
+            prefetched: Mutex::new(HashMap::new()),
+            prefetch_permits: Arc::new(Semaphore::new(DEFAULT_PREFETCH_CONCURRENCY)),
         })
     }
+
+    /// Reuse audio for text that was synthesized before with the same settings
+    pub fn set_cache(&mut self, cache: AudioCache) {
+        self.requester.cache = Some(cache);
+    }
+
+    /// Give up on a request when the API takes longer than this to respond
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.requester.timeout = timeout;
+    }
+
+    /// Requests allowed per minute (0 for no limit), and retries of
+    /// rate-limited or failed requests
+    pub fn set_limits(&mut self, requests_per_minute: usize, max_retries: u32) {
+        self.requester.limiter = RateLimiter::per_minute(requests_per_minute);
+        self.requester.max_retries = max_retries;
+    }
+
+    /// Price of the model in USD per million characters, if known, and the
+    /// session spend at which synthesis stops
+    pub fn set_pricing(&mut self, price_per_million: Option<f64>, budget: Option<f64>) {
+        self.requester.price_per_million = price_per_million;
+        self.requester.usage.set_budget(budget);
+    }
+
+    /// How many prefetch requests may run at once
+    pub fn set_prefetch_concurrency(&mut self, concurrency: usize) {
+        self.prefetch_permits = Arc::new(Semaphore::new(concurrency.max(1)));
+    }
+}
+
+impl Drop for OpenAITtsEngine {
+    fn drop(&mut self) {
+        for (_, task) in self
+            .prefetched
+            .lock()
+            .expect("prefetch lock poisoned")
+            .drain()
+        {
+            task.abort();
+        }
+    }
 }
 
 impl TextToSpeech for OpenAITtsEngine {
-    fn speak(&self, text: &str) -> Result<()> {
+    fn speak(&self, speech: &Markup, prosody: Prosody) -> Result<()> {
+        let text = speech.to_plain();
         if self.is_speaking.load(Ordering::SeqCst) {
             tracing::debug!("Already speaking with OpenAI TTS, skipping text: {}", text);
             return Ok(());
         }
 
+        // Fail right away so a fallback engine can take over
+        if self.requester.usage.over_budget() {
+            return Err(budget_error().into());
+        }
+
+        // Opens the audio output the first time anything is spoken
+        let player = Player::shared()?;
+
         // Mark as speaking
         self.is_speaking.store(true, Ordering::SeqCst);
+        self.stop_requested.store(false, Ordering::SeqCst);
+        *self.last_error.lock().expect("error lock poisoned") = None;
         let is_speaking = self.is_speaking.clone();
-
-        // Clone required values for async task
-        let api_key = self.api_key.clone();
-        let model = self.model.clone();
-        let voice = self.voice.clone();
-        let client = self.client.clone();
-        let text = text.to_string();
-        let temp_dir = self.temp_dir.clone();
+        let stop_requested = self.stop_requested.clone();
+        let last_error = self.last_error.clone();
+
+        let requester = self.requester.clone();
+        let speed = openai_speed(prosody);
+        let cache_key = requester.cache_key(&text, speed);
+        let prefetched = self
+            .prefetched
+            .lock()
+            .expect("prefetch lock poisoned")
+            .remove(&cache_key);
 
         // Spawn async task for TTS
         tokio::spawn(async move {
-            let result = async {
-                // Create request JSON
-                let json = serde_json::json!({
-                    "model": model,
-                    "input": text,
-                    "voice": voice,
-                    "response_format": "mp3"
-                });
+            // Playback starts while the audio is still downloading
+            let (mut feed, finished) = PcmFeed::new(player, prosody, stop_requested.clone());
+
+            let result: Result<()> = async {
+                if let Some(task) = prefetched {
+                    match task.await {
+                        Ok(Ok(audio)) => return feed.push(&audio),
+                        Ok(Err(e)) => tracing::debug!("Prefetch failed, retrying: {}", e),
+                        Err(e) => tracing::debug!("Prefetch did not finish: {}", e),
+                    }
+                }
 
-                tracing::debug!("Sending TTS request to OpenAI API for text: {}", text);
-
-                // Send request to OpenAI API
-                let response = client
-                    .post("https://api.openai.com/v1/audio/speech")
-                    .header("Content-Type", "application/json")
-                    .header("Authorization", format!("Bearer {}", api_key))
-                    .json(&json)
-                    .send()
-                    .await?;
-
-                // Check for error
-                if !response.status().is_success() {
-                    let error_text = response.text().await?;
-                    return Err(anyhow::anyhow!("OpenAI API error: {}", error_text));
+                let cache = requester.cache.as_ref();
+                if let Some(audio) = cache.and_then(|cache| cache.get(&cache_key)) {
+                    return feed.push(&audio);
                 }
 
-                // Get the audio bytes
-                let bytes = response.bytes().await?;
-                tracing::debug!("Received {} bytes of audio from OpenAI", bytes.len());
+                let mut response = requester.request(&text, speed).await?;
+                let mut audio = Vec::new();
+                while let Some(chunk) = response.chunk().await? {
+                    // Stop downloading once playback is stopped
+                    if stop_requested.load(Ordering::SeqCst) {
+                        return Ok(());
+                    }
+                    feed.push(&chunk)?;
+                    audio.extend_from_slice(&chunk);
+                }
+                tracing::debug!("Received {} bytes of audio from OpenAI", audio.len());
 
-                // Save to temporary file
-                let temp_file_path =
-                    temp_dir.join(format!("tts_{}.mp3", chrono::Utc::now().timestamp_millis()));
-                let mut file = File::create(&temp_file_path).await?;
-                file.write_all(&bytes).await?;
-                file.flush().await?;
-                drop(file);
+                if let Some(cache) = cache {
+                    cache.put(&cache_key, &audio);
+                }
+                Ok(())
+            }
+            .await;
 
-                tracing::debug!("Saved audio to temporary file: {:?}", temp_file_path);
+            // Ends the stream once everything downloaded has played
+            let started = feed.started();
+            let result = result.and(feed.finish());
+            while !finished.load(Ordering::SeqCst) {
+                tokio::time::sleep(STOP_POLL_INTERVAL).await;
+            }
 
-                // Play the audio using rodio
-                let audio_bytes = bytes.to_vec();
-                tokio::task::spawn_blocking(move || -> Result<()> {
-                    let cursor = Cursor::new(audio_bytes);
+            // Log any errors. A message that failed before any of it played
+            // can be retried by another engine.
+            if let Err(e) = result {
+                tracing::error!("OpenAI TTS error: {}", e);
+                if !started {
+                    *last_error.lock().expect("error lock poisoned") = Some(e.to_string());
+                }
+            }
 
-                    // Initialize audio output
-                    let (_stream, stream_handle) = rodio::OutputStream::try_default()?;
-                    let sink = rodio::Sink::try_new(&stream_handle)?;
+            // Reset speaking flag regardless of result
+            is_speaking.store(false, Ordering::SeqCst);
+        });
 
-                    // Load and play the audio
-                    sink.append(rodio::Decoder::new(cursor)?);
+        Ok(())
+    }
 
-                    // Wait for playback to complete
-                    sink.sleep_until_end();
+    fn is_speaking(&self) -> bool {
+        self.is_speaking.load(Ordering::SeqCst)
+    }
 
-                    tracing::debug!("OpenAI TTS audio playback completed");
-                    Ok(())
-                })
-                .await??;
+    fn stop(&self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        // A paused stream never reads the stop flag, so it is also taken out
+        // of the player
+        if self.is_speaking() {
+            if let Some(player) = Player::opened() {
+                log_player_error(player.stop_current());
+            }
+        }
+    }
 
-                // Try to clean up temp file
-                if let Err(e) = tokio::fs::remove_file(&temp_file_path).await {
-                    tracing::warn!("Failed to clean up temp file: {}", e);
-                }
+    fn pause(&self) {
+        if let Some(player) = Player::opened() {
+            log_player_error(player.pause());
+        }
+    }
 
-                Ok(())
-            }
-            .await;
+    fn resume(&self) {
+        if let Some(player) = Player::opened() {
+            log_player_error(player.resume());
+        }
+    }
 
-            // Reset speaking flag regardless of result
-            is_speaking.store(false, Ordering::SeqCst);
+    fn current_voice(&self) -> Option<String> {
+        Some(self.requester.voice.clone())
+    }
 
-            // Log any errors
-            if let Err(e) = result {
-                tracing::error!("OpenAI TTS error: {}", e);
+    fn take_error(&self) -> Option<String> {
+        self.last_error.lock().expect("error lock poisoned").take()
+    }
+
+    fn status(&self) -> Option<String> {
+        Some(self.requester.usage.summary())
+    }
+
+    fn prefetch(&self, upcoming: &[String], prosody: Prosody) {
+        let speed = openai_speed(prosody);
+        let upcoming: Vec<(String, &String)> = upcoming
+            .iter()
+            .map(|text| (self.requester.cache_key(text, speed), text))
+            .collect();
+        let mut prefetched = self.prefetched.lock().expect("prefetch lock poisoned");
+
+        // Messages that left the queue, e.g. muted authors, or that will now be
+        // read at a different speed are not needed anymore
+        prefetched.retain(|key, task| {
+            let wanted = upcoming.iter().any(|(k, _)| k == key);
+            if !wanted {
+                task.abort();
             }
+            wanted
         });
 
-        Ok(())
+        for (key, text) in upcoming {
+            if prefetched.contains_key(&key) {
+                continue;
+            }
+
+            let requester = self.requester.clone();
+            let permits = self.prefetch_permits.clone();
+            let input = text.clone();
+            let task = tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await?;
+                requester.synthesize(&input, speed).await
+            });
+            prefetched.insert(key, task);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcm_samples_split_across_chunks() {
+        let mut pending = vec![0x01, 0x00, 0xff];
+        assert_eq!(take_pcm_samples(&mut pending), vec![1]);
+        assert_eq!(pending, vec![0xff]);
+
+        pending.extend_from_slice(&[0xff, 0x00]);
+        assert_eq!(take_pcm_samples(&mut pending), vec![-1]);
+        assert_eq!(pending, vec![0x00]);
     }
 }
@@ -0,0 +1,422 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    time::sleep,
+};
+
+use crate::backoff::Backoff;
+use crate::error::AppError;
+use crate::source::{ChatMessage, ChatSource, MAX_RECONNECTS};
+
+const TWITCH_IRC_ADDR: &str = "irc.chat.twitch.tv:6667";
+
+/// Read-only Twitch chat, joined anonymously over IRC
+pub struct TwitchChat {
+    addr: String,
+    channel: String,
+    connection: Option<Connection>,
+    backoff: Backoff,
+}
+
+struct Connection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl TwitchChat {
+    /// `channel` may be a channel name, `#name` or a twitch.tv URL
+    pub fn new(channel: &str) -> Self {
+        Self::with_server(TWITCH_IRC_ADDR, channel)
+    }
+
+    pub fn with_server(addr: &str, channel: &str) -> Self {
+        let channel = channel.trim();
+        let channel = channel
+            .rsplit_once("twitch.tv/")
+            .map_or(channel, |(_, name)| name)
+            .trim_start_matches('#')
+            .trim_end_matches('/')
+            .to_lowercase();
+
+        Self {
+            addr: addr.to_string(),
+            channel,
+            connection: None,
+            backoff: Backoff::new(Duration::from_secs(1), Duration::from_secs(60)),
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let stream = TcpStream::connect(&self.addr).await.map_err(AppError::IO)?;
+        let (reader, mut writer) = stream.into_split();
+
+        // Any justinfan nick logs in anonymously with read-only access
+        let nick = format!("justinfan{}", fastrand::u32(10_000..100_000));
+        let handshake = format!(
+            "CAP REQ :twitch.tv/tags twitch.tv/commands\r\nPASS SCHMOOPIIE\r\nNICK {}\r\nJOIN #{}\r\n",
+            nick, self.channel
+        );
+        writer.write_all(handshake.as_bytes()).await?;
+
+        Ok(Connection {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        })
+    }
+
+    async fn wait_before_reconnect(&mut self, err: anyhow::Error) -> Result<()> {
+        self.connection = None;
+
+        if self.backoff.attempts() >= MAX_RECONNECTS {
+            tracing::error!("Giving up on Twitch chat #{}", self.channel);
+            return Err(err);
+        }
+
+        let delay = self.backoff.next_delay();
+        tracing::warn!(
+            "Twitch chat #{} disconnected: {}. Reconnecting in {:.1}s",
+            self.channel,
+            err,
+            delay.as_secs_f64()
+        );
+        sleep(delay).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChatSource for TwitchChat {
+    async fn next_message(&mut self) -> Result<Option<ChatMessage>> {
+        loop {
+            if self.connection.is_none() {
+                match self.connect().await {
+                    Ok(connection) => {
+                        tracing::info!("Joined Twitch chat #{}", self.channel);
+                        self.connection = Some(connection);
+                    }
+                    Err(e) => {
+                        self.wait_before_reconnect(e).await?;
+                        continue;
+                    }
+                }
+            }
+
+            let connection = self.connection.as_mut().expect("connected above");
+            let line = match connection.lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    self.wait_before_reconnect(anyhow::anyhow!("connection closed"))
+                        .await?;
+                    continue;
+                }
+                Err(e) => {
+                    self.wait_before_reconnect(e.into()).await?;
+                    continue;
+                }
+            };
+
+            let Some(message) = IrcMessage::parse(&line) else {
+                continue;
+            };
+
+            match message.command {
+                "PING" => {
+                    let pong = format!("PONG :{}\r\n", message.params.first().unwrap_or(&""));
+                    if let Err(e) = connection.writer.write_all(pong.as_bytes()).await {
+                        self.wait_before_reconnect(e.into()).await?;
+                    }
+                }
+                // Sent once the channel is joined, so the connection is working
+                "ROOMSTATE" => self.backoff.reset(),
+                "PRIVMSG" => {
+                    if let Some(chat_message) = message.to_chat_message() {
+                        return Ok(Some(chat_message));
+                    }
+                }
+                "RECONNECT" => {
+                    tracing::info!("Twitch asked to reconnect");
+                    self.connection = None;
+                }
+                "NOTICE" => {
+                    tracing::warn!("Twitch notice: {}", message.params.last().unwrap_or(&""));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// One line of IRC with IRCv3 tags:
+// @key=value;key2=value2 :nick!user@host COMMAND param :trailing param
+#[derive(Debug)]
+struct IrcMessage<'a> {
+    tags: HashMap<&'a str, String>,
+    prefix: Option<&'a str>,
+    command: &'a str,
+    params: Vec<&'a str>,
+}
+
+impl<'a> IrcMessage<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        let mut tags = HashMap::new();
+        if let Some(tagged) = rest.strip_prefix('@') {
+            let (raw_tags, after) = tagged.split_once(' ')?;
+            for tag in raw_tags.split(';') {
+                let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                tags.insert(key, unescape_tag(value));
+            }
+            rest = after.trim_start();
+        }
+
+        let mut prefix = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (p, after) = prefixed.split_once(' ')?;
+            prefix = Some(p);
+            rest = after.trim_start();
+        }
+
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing);
+                break;
+            }
+            let (param, after) = rest.split_once(' ').unwrap_or((rest, ""));
+            params.push(param);
+            rest = after;
+        }
+
+        Some(Self {
+            tags,
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .get(key)
+            .map(String::as_str)
+            .filter(|v| !v.is_empty())
+    }
+
+    fn to_chat_message(&self) -> Option<ChatMessage> {
+        let raw_text = *self.params.get(1)?;
+        // /me messages arrive wrapped as CTCP ACTION
+        let text = raw_text
+            .strip_prefix("\u{1}ACTION ")
+            .map(|t| t.trim_end_matches('\u{1}'))
+            .unwrap_or(raw_text);
+
+        let nick = self
+            .prefix
+            .map(|p| p.split('!').next().unwrap_or(p))
+            .unwrap_or_default();
+        let author = self.tag("display-name").unwrap_or(nick).to_string();
+
+        let sent_at = self
+            .tag("tmi-sent-ts")
+            .and_then(|ts| ts.parse::<i64>().ok())
+            .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+            .unwrap_or_else(Utc::now);
+
+        let id = self
+            .tag("id")
+            .map(String::from)
+            .unwrap_or_else(|| format!("{}-{}", nick, sent_at.timestamp_millis()));
+
+        Some(ChatMessage {
+            id,
+            author,
+            text: text.to_string(),
+            timestamp: sent_at.to_rfc3339(),
+            source: String::new(),
+            roles: self.tag("badges").map(badge_roles).unwrap_or_default(),
+            amount: self
+                .tag("bits")
+                .filter(|bits| *bits != "0")
+                .map(|bits| format!("{} bits", bits)),
+        })
+    }
+}
+
+// "broadcaster/1,subscriber/12" -> ["owner", "member"], keeping unknown badges as is
+fn badge_roles(badges: &str) -> Vec<String> {
+    badges
+        .split(',')
+        .filter_map(|badge| badge.split('/').next())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            match name {
+                "broadcaster" => "owner",
+                "subscriber" | "founder" => "member",
+                "partner" => "verified",
+                other => other,
+            }
+            .to_string()
+        })
+        .collect()
+}
+
+fn unescape_tag(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => result.push(';'),
+            Some('s') => result.push(' '),
+            Some('r') => result.push('\r'),
+            Some('n') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    const PRIVMSG: &str = "@badge-info=subscriber/8;badges=broadcaster/1,subscriber/6;bits=100;\
+        color=#FF0000;display-name=Streamer;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;\
+        mod=0;tmi-sent-ts=1700000000123;user-id=123 \
+        :streamer!streamer@streamer.tmi.twitch.tv PRIVMSG #streamer :Cheer100 hello\\sthere world\r\n";
+
+    #[test]
+    fn parses_tagged_privmsg() {
+        let message = IrcMessage::parse(PRIVMSG).unwrap();
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(
+            message.params,
+            vec!["#streamer", "Cheer100 hello\\sthere world"]
+        );
+
+        let chat = message.to_chat_message().unwrap();
+        assert_eq!(chat.id, "b34ccfc7-4977-403a-8a94-33c6bac34fb8");
+        assert_eq!(chat.author, "Streamer");
+        assert_eq!(chat.roles, vec!["owner", "member"]);
+        assert_eq!(chat.amount.as_deref(), Some("100 bits"));
+        assert_eq!(chat.timestamp, "2023-11-14T22:13:20.123+00:00");
+    }
+
+    #[test]
+    fn parses_action_and_escaped_tags() {
+        let line =
+            "@display-name=Some\\sOne;id=1 :someone!someone@x PRIVMSG #ch :\u{1}ACTION waves\u{1}";
+        let chat = IrcMessage::parse(line).unwrap().to_chat_message().unwrap();
+        assert_eq!(chat.author, "Some One");
+        assert_eq!(chat.text, "waves");
+        assert!(chat.roles.is_empty());
+        assert_eq!(chat.amount, None);
+    }
+
+    #[test]
+    fn falls_back_to_nick_without_tags() {
+        let line = ":viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #ch :hi";
+        let chat = IrcMessage::parse(line).unwrap().to_chat_message().unwrap();
+        assert_eq!(chat.author, "viewer");
+        assert_eq!(chat.text, "hi");
+    }
+
+    #[test]
+    fn normalizes_channel_names() {
+        for input in ["Streamer", "#streamer", "https://www.twitch.tv/streamer/"] {
+            assert_eq!(TwitchChat::new(input).channel, "streamer", "{}", input);
+        }
+    }
+
+    // Accept one client, check its handshake and return the reader and writer
+    async fn accept(listener: &TcpListener) -> (Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let mut handshake = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let done = line.starts_with("JOIN");
+            handshake.push(line);
+            if done {
+                break;
+            }
+        }
+
+        assert_eq!(handshake[0], "CAP REQ :twitch.tv/tags twitch.tv/commands");
+        assert!(handshake[2].starts_with("NICK justinfan"));
+        assert_eq!(handshake[3], "JOIN #streamer");
+
+        (lines, writer)
+    }
+
+    #[tokio::test]
+    async fn reads_from_fake_irc_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server = tokio::spawn(async move {
+            let (mut lines, mut writer) = accept(&listener).await;
+            writer.write_all(b"PING :tmi.twitch.tv\r\n").await.unwrap();
+            writer.write_all(PRIVMSG.as_bytes()).await.unwrap();
+            lines.next_line().await.unwrap().unwrap()
+        });
+
+        let mut chat = TwitchChat::with_server(&addr, "#Streamer");
+        let message = chat.next_message().await.unwrap().unwrap();
+        assert_eq!(message.author, "Streamer");
+        assert_eq!(message.text, "Cheer100 hello\\sthere world");
+
+        assert_eq!(server.await.unwrap(), "PONG :tmi.twitch.tv");
+    }
+
+    #[tokio::test]
+    async fn reconnects_when_asked() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (_lines, mut writer) = accept(&listener).await;
+            writer
+                .write_all(b":tmi.twitch.tv RECONNECT\r\n")
+                .await
+                .unwrap();
+
+            let (_lines, mut writer) = accept(&listener).await;
+            writer
+                .write_all(b":viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #streamer :back\r\n")
+                .await
+                .unwrap();
+            // Keep the connection open until the client has read the message
+            std::future::pending::<()>().await;
+        });
+
+        let mut chat = TwitchChat::with_server(&addr, "streamer");
+        let message = chat.next_message().await.unwrap().unwrap();
+        assert_eq!(message.author, "viewer");
+        assert_eq!(message.text, "back");
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// Characters sent for synthesis and what they are estimated to cost. Clones
+/// share the same totals.
+#[derive(Debug, Clone, Default)]
+pub struct TtsUsage {
+    totals: Arc<Mutex<Totals>>,
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    characters: u64,
+    requests: u64,
+    // USD, for characters whose model price is known
+    cost: Option<f64>,
+    budget: Option<f64>,
+}
+
+// Totals for the whole session, kept when engines are recreated
+static SESSION_USAGE: OnceLock<TtsUsage> = OnceLock::new();
+
+impl TtsUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Usage shared by every engine created this session
+    pub fn session() -> Self {
+        SESSION_USAGE.get_or_init(Self::new).clone()
+    }
+
+    /// Spend in USD at which synthesis stops, or `None` for no limit
+    pub fn set_budget(&self, budget: Option<f64>) {
+        self.totals.lock().expect("usage lock poisoned").budget = budget;
+    }
+
+    /// Count a successful synthesis request for `text`, priced in USD per
+    /// million characters if the price is known
+    pub fn record(&self, text: &str, price_per_million: Option<f64>) {
+        let characters = text.chars().count() as u64;
+        let mut totals = self.totals.lock().expect("usage lock poisoned");
+        totals.characters += characters;
+        totals.requests += 1;
+        if let Some(price) = price_per_million {
+            *totals.cost.get_or_insert(0.0) += characters as f64 * price / 1_000_000.0;
+        }
+    }
+
+    pub fn characters(&self) -> u64 {
+        self.totals.lock().expect("usage lock poisoned").characters
+    }
+
+    /// Estimated spend in USD, if any priced model was used
+    pub fn estimated_cost(&self) -> Option<f64> {
+        self.totals.lock().expect("usage lock poisoned").cost
+    }
+
+    /// Whether the estimated spend has reached the budget
+    pub fn over_budget(&self) -> bool {
+        let totals = self.totals.lock().expect("usage lock poisoned");
+        match (totals.budget, totals.cost) {
+            (Some(budget), Some(cost)) => cost >= budget,
+            _ => false,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let totals = self.totals.lock().expect("usage lock poisoned");
+        let mut summary = format!(
+            "{} characters in {} requests",
+            totals.characters, totals.requests
+        );
+
+        if let Some(cost) = totals.cost {
+            summary.push_str(&format!(", ~${:.4}", cost));
+        }
+        if let Some(budget) = totals.budget {
+            summary.push_str(&format!(" (budget ${:.2})", budget));
+        }
+        summary
+    }
+}
+
+/// Spaces out requests so no more than `limit` start in any `window`.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    started: Arc<Mutex<VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            started: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn per_minute(limit: usize) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// Wait until another request may start. A limit of 0 never waits.
+    pub async fn acquire(&self) {
+        if self.limit == 0 {
+            return;
+        }
+
+        loop {
+            let wait = {
+                let mut started = self.started.lock().expect("rate limiter lock poisoned");
+                let now = Instant::now();
+                while started
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) >= self.window)
+                {
+                    started.pop_front();
+                }
+
+                if started.len() < self.limit {
+                    started.push_back(now);
+                    return;
+                }
+                self.window - now.duration_since(started[0])
+            };
+
+            tracing::debug!(
+                "TTS request limit reached, waiting {:.1}s",
+                wait.as_secs_f64()
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_spend_against_budget() {
+        // A dollar per five characters
+        let usage = TtsUsage::new();
+        usage.set_budget(Some(1.5));
+        usage.record("こんにちは", Some(200_000.0));
+        assert_eq!(usage.characters(), 5);
+        assert!(!usage.over_budget());
+
+        usage.record("hello", Some(200_000.0));
+        assert!(usage.over_budget());
+        assert_eq!(
+            usage.summary(),
+            "10 characters in 2 requests, ~$2.0000 (budget $1.50)"
+        );
+
+        let unpriced = TtsUsage::new();
+        unpriced.set_budget(Some(0.0));
+        unpriced.record("hello", None);
+        assert!(!unpriced.over_budget());
+        assert_eq!(unpriced.estimated_cost(), None);
+    }
+
+    #[tokio::test]
+    async fn limits_requests_per_window() {
+        let limiter = RateLimiter::new(2, Duration::from_millis(100));
+        let started = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        limiter.acquire().await;
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+}
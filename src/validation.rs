@@ -0,0 +1,377 @@
+use std::fmt;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::{self, Config, SourceConfig, TtsEngine};
+use crate::error::AppError;
+use crate::filter::Filter;
+
+// Models and voices offered by the OpenAI speech API. New ones only produce a
+// warning, so the bot keeps working when OpenAI adds more.
+const OPENAI_MODELS: [&str; 3] = ["tts-1", "tts-1-hd", "gpt-4o-mini-tts"];
+const OPENAI_VOICES: [&str; 11] = [
+    "alloy", "ash", "ballad", "coral", "echo", "fable", "nova", "onyx", "sage", "shimmer", "verse",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Probably a mistake, but the bot can run
+    Warning,
+    /// The bot refuses to start
+    Error,
+}
+
+/// Something wrong with a configuration file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    /// The setting concerned, e.g. "filter.max_length", or empty for the whole file
+    pub key: String,
+    pub message: String,
+}
+
+impl Problem {
+    fn error(key: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn warning(key: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        if self.key.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.key, self.message)
+        }
+    }
+}
+
+/// Log warnings about a loaded config, and fail listing every error
+pub fn validate(table: &toml::Table, config: &Config) -> Result<()> {
+    let mut errors = Vec::new();
+    for problem in check_keys(table).into_iter().chain(check_values(config)) {
+        match problem.severity {
+            Severity::Warning => tracing::warn!("Config {}", problem),
+            Severity::Error => errors.push(problem.to_string()),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Config(format!("invalid configuration\n{}", errors.join("\n"))).into())
+    }
+}
+
+/// Every problem in a config file, including ones that stop it from loading
+pub fn check(table: &toml::Table) -> Vec<Problem> {
+    let mut problems = check_keys(table);
+    match config::config_from_table(table) {
+        Ok(config) => problems.extend(check_values(&config)),
+        Err(e) => problems.push(Problem::error("", e.to_string())),
+    }
+    problems
+}
+
+// Top-level keys of a settings struct, as they appear in the file
+fn known_keys<T: Serialize>(value: &T) -> Vec<String> {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::Object(map)) => map.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Keys that no setting reads, with the closest known key as a suggestion
+pub fn check_keys(table: &toml::Table) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    check_table(table, "", &known_keys(&Config::default()), &mut problems);
+
+    if let Some(toml::Value::Table(filter)) = table.get("filter") {
+        check_table(
+            filter,
+            "filter.",
+            &known_keys(&Filter::default()),
+            &mut problems,
+        );
+    }
+    if let Some(toml::Value::Table(sources)) = table.get("sources") {
+        let known = known_keys(&SourceConfig::default());
+        for (label, source) in sources {
+            if let toml::Value::Table(source) = source {
+                check_table(
+                    source,
+                    &format!("sources.{}.", label),
+                    &known,
+                    &mut problems,
+                );
+            }
+        }
+    }
+
+    problems
+}
+
+fn check_table(table: &toml::Table, prefix: &str, known: &[String], problems: &mut Vec<Problem>) {
+    for key in table.keys() {
+        if known.contains(key) {
+            continue;
+        }
+
+        let closest = known
+            .iter()
+            .map(|k| (strsim::levenshtein(key, k), k))
+            .min()
+            .filter(|(distance, _)| *distance <= (key.len() / 3).max(2));
+        let message = match closest {
+            Some((_, suggestion)) => format!("unknown setting, did you mean `{}`?", suggestion),
+            None => "unknown setting".to_string(),
+        };
+        problems.push(Problem::warning(&format!("{}{}", prefix, key), message));
+    }
+}
+
+/// Values that are out of range, inconsistent or missing for the chosen engines
+pub fn check_values(config: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut range = |key: &str, value: f64, min: f64, max: f64| {
+        if !(min..=max).contains(&value) {
+            problems.push(Problem::error(
+                key,
+                format!("{} is out of range, expected {} to {}", value, min, max),
+            ));
+        }
+    };
+
+    range(
+        "poll_interval_ms",
+        config.poll_interval_ms as f64,
+        500.0,
+        600_000.0,
+    );
+    range(
+        "wait_poll_interval_secs",
+        config.wait_poll_interval_secs as f64,
+        10.0,
+        86_400.0,
+    );
+    range(
+        "quota_warn_percent",
+        config.quota_warn_percent as f64,
+        0.0,
+        100.0,
+    );
+    range(
+        "expected_stream_hours",
+        config.expected_stream_hours,
+        0.1,
+        24.0,
+    );
+    range(
+        "tts_timeout_secs",
+        config.tts_timeout_secs as f64,
+        1.0,
+        300.0,
+    );
+    range("rate", config.rate as f64, 0.25, 4.0);
+    range("pitch", config.pitch as f64, 0.5, 2.0);
+    range("volume", config.volume as f64, 0.0, 2.0);
+    range("max_rate", config.max_rate as f64, 0.25, 4.0);
+    range("author_rate", config.author_rate as f64, 0.25, 4.0);
+    range("author_pitch", config.author_pitch as f64, 0.5, 2.0);
+    if let Some(budget) = config.openai_budget_usd {
+        range("openai_budget_usd", budget, 0.0, f64::MAX);
+    }
+
+    if config.adaptive_rate && config.max_rate <= config.rate {
+        problems.push(Problem::warning(
+            "max_rate",
+            "adaptive_rate has no effect unless max_rate is above rate",
+        ));
+    }
+
+    if config.api_key.is_empty() || config.api_key == "YOUR_API_KEY_HERE" {
+        problems.push(Problem::warning(
+            "api_key",
+            "not set, so YouTube chats can't be read",
+        ));
+    }
+
+    let engines: Vec<&TtsEngine> = std::iter::once(&config.tts_engine)
+        .chain(&config.fallback_engines)
+        .collect();
+    if engines.contains(&&TtsEngine::OpenAI) {
+        check_openai(config, &mut problems);
+    }
+    if engines.contains(&&TtsEngine::Windows) && config.volume > 1.0 {
+        problems.push(Problem::warning(
+            "volume",
+            format!(
+                "{} is louder than Windows voices can play, they will use 1.0",
+                config.volume
+            ),
+        ));
+    }
+    for (i, engine) in engines.iter().enumerate() {
+        if engines[..i].contains(engine) {
+            problems.push(Problem::warning(
+                "fallback_engines",
+                format!("{:?} is listed more than once", engine),
+            ));
+        }
+    }
+
+    problems
+}
+
+fn check_openai(config: &Config, problems: &mut Vec<Problem>) {
+    match config.openai_api_key.as_deref() {
+        None | Some("") | Some("YOUR_OPENAI_API_KEY_HERE") => problems.push(Problem::error(
+            "openai_api_key",
+            "required for the OpenAI TTS engine",
+        )),
+        Some(_) => {}
+    }
+
+    if !OPENAI_MODELS.contains(&config.openai_model.as_str()) {
+        problems.push(Problem::warning(
+            "openai_model",
+            format!(
+                "unknown model `{}`, expected one of {}",
+                config.openai_model,
+                OPENAI_MODELS.join(", ")
+            ),
+        ));
+    }
+    if !OPENAI_VOICES.contains(&config.openai_voice.as_str()) {
+        problems.push(Problem::warning(
+            "openai_voice",
+            format!(
+                "unknown voice `{}`, expected one of {}",
+                config.openai_voice,
+                OPENAI_VOICES.join(", ")
+            ),
+        ));
+    }
+
+    if config.openai_budget_usd.is_some()
+        && !config.openai_pricing.contains_key(&config.openai_model)
+    {
+        problems.push(Problem::warning(
+            "openai_pricing",
+            format!(
+                "no price for `{}`, so openai_budget_usd can't be enforced",
+                config.openai_model
+            ),
+        ));
+    }
+}
+
+/// Whether the configured Windows voice is one of the installed voices
+pub fn check_windows_voice(config: &Config, installed: &[String]) -> Option<Problem> {
+    let voice = &config.windows_voice;
+    if installed.iter().any(|name| name.contains(voice.as_str())) {
+        return None;
+    }
+    Some(Problem::warning(
+        "windows_voice",
+        format!(
+            "`{}` is not installed, the default voice will be used. Installed: {}",
+            voice,
+            installed.join(", ")
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_known_keys() {
+        let table: toml::Table = toml::from_str(
+            r#"
+            api_key = "key"
+            openai_vioce = "nova"
+            something_else = 1
+
+            [filter]
+            max_lenght = 10
+
+            [sources.main]
+            message_template = "{text}"
+            "#,
+        )
+        .unwrap();
+
+        let problems = check_keys(&table);
+        let messages: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "warning: openai_vioce: unknown setting, did you mean `openai_voice`?",
+                "warning: something_else: unknown setting",
+                "warning: filter.max_lenght: unknown setting, did you mean `max_length`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_config_is_valid() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml.sample");
+        let problems = check(&config::read_table(path).unwrap());
+        let unexpected: Vec<&Problem> = problems.iter().filter(|p| p.key != "api_key").collect();
+        assert!(unexpected.is_empty(), "{:?}", unexpected);
+    }
+
+    #[test]
+    fn reports_every_invalid_value() {
+        let config = Config {
+            api_key: "key".to_string(),
+            poll_interval_ms: 0,
+            rate: 10.0,
+            volume: 1.5,
+            tts_engine: TtsEngine::OpenAI,
+            fallback_engines: vec![TtsEngine::Windows],
+            openai_voice: "robot".to_string(),
+            ..Config::default()
+        };
+
+        let problems = check_values(&config);
+        let keys: Vec<(&str, Severity)> = problems
+            .iter()
+            .map(|p| (p.key.as_str(), p.severity))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("poll_interval_ms", Severity::Error),
+                ("rate", Severity::Error),
+                ("openai_api_key", Severity::Error),
+                ("openai_voice", Severity::Warning),
+                ("volume", Severity::Warning),
+            ]
+        );
+        assert!(check_values(&Config {
+            api_key: "key".to_string(),
+            ..Config::default()
+        })
+        .is_empty());
+    }
+}
@@ -1,28 +1,196 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Result;
+use async_trait::async_trait;
 use chrono::DateTime;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use tokio::time::{sleep, Duration};
 
-use crate::error::AppError;
+use crate::backoff::Backoff;
+use crate::error::{self, AppError};
+use crate::quota::{Endpoint, QuotaTracker};
+use crate::recorder::Recorder;
+pub use crate::source::ChatMessage;
+use crate::source::ChatSource;
+use crate::target::Target;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChatMessage {
-    pub id: String,
-    pub author: String,
-    pub text: String,
-    pub timestamp: String,
+// Initial delay before retrying a failed request
+const RETRY_BASE_DELAY_MS: u64 = 1000;
+
+// Number of recent message IDs remembered to avoid reading a message twice
+const MAX_SEEN_IDS: usize = 2000;
+
+// Messages older than this when first connecting to a chat are ignored by default
+const DEFAULT_BACKLOG_MAX_AGE_SECS: u64 = 30;
+
+// How often the chat position is saved while a page of messages is being read
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+// Position in a live chat, saved so that a restarted bot continues where it left off
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct ChatState {
+    video_id: String,
+    live_chat_id: Option<String>,
+    next_page_token: Option<String>,
+    seen_ids: Vec<String>,
+}
+
+impl ChatState {
+    // A file that can't be read or parsed is ignored rather than stopping the bot
+    fn load(path: &Path) -> Option<Self> {
+        if !path.exists() {
+            return None;
+        }
+        let result = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(serde_json::from_str(&json)?));
+        match result {
+            Ok(state) => Some(state),
+            Err(e) => {
+                tracing::warn!(
+                    "Ignoring unreadable state file {:?}, starting fresh: {}",
+                    path,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    // Written to a temporary file and renamed, so a crash never leaves half a file
+    fn save(&self, path: &Path) -> Result<()> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut file = tempfile::NamedTempFile::new_in(dir)?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())?;
+        file.persist(path)?;
+        Ok(())
+    }
 }
 
 pub struct ChatMonitor {
     client: Client,
     video_id: String,
     api_key: String,
+    live_chat_id: Option<String>,
     next_page_token: Option<String>,
+    // Page token that fetched the messages still waiting in `pending`
+    pending_page_token: Option<String>,
+    pending: VecDeque<ChatMessage>,
+    seen_ids: VecDeque<String>,
+    seen_set: HashSet<String>,
+    state_file: Option<PathBuf>,
+    last_saved: Option<Instant>,
     poll_interval_ms: u64,
     last_processed_time: u64,
+    backlog: BacklogPolicy,
+    // Set until the first page after the first connect has been discarded
+    discard_next_page: bool,
+    connected_once: bool,
+    max_retries: u32,
+    backoff: Backoff,
+    quota: Option<QuotaTracker>,
+    recorder: Option<Recorder>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn author_roles(details: &serde_json::Value) -> Vec<String> {
+    [
+        ("isChatOwner", "owner"),
+        ("isChatModerator", "moderator"),
+        ("isChatSponsor", "member"),
+        ("isVerified", "verified"),
+    ]
+    .iter()
+    .filter(|(field, _)| details[*field].as_bool() == Some(true))
+    .map(|(_, role)| role.to_string())
+    .collect()
+}
+
+// How the chat history returned when connecting to a chat is told apart from
+// new messages
+#[derive(Debug, Clone, Copy)]
+struct BacklogPolicy {
+    max_age_secs: u64,
+    skip_initial_page: bool,
+}
+
+impl Default for BacklogPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_secs: DEFAULT_BACKLOG_MAX_AGE_SECS,
+            skip_initial_page: false,
+        }
+    }
+}
+
+impl BacklogPolicy {
+    // The publish time before which messages are backlog, and whether the whole
+    // first page is. Only the first connect discards a page: on a reconnect or a
+    // resumed chat the backlog was dealt with before.
+    fn on_connect(&self, cutoff: u64, connected_at: u64, connected_once: bool) -> (u64, bool) {
+        let cutoff = cutoff.max(connected_at.saturating_sub(self.max_age_secs));
+        (cutoff, self.skip_initial_page && !connected_once)
+    }
+}
+
+fn is_backlog(published_at: u64, cutoff: u64) -> bool {
+    published_at < cutoff
+}
+
+// Where a video is as a stream, read from a `videos` item with the snippet and
+// liveStreamingDetails parts
+#[derive(Debug, PartialEq)]
+enum StreamStatus {
+    Live,
+    Upcoming,
+    Ended,
+    // Not a stream, or a live stream without a chat
+    NoChat,
+}
+
+fn stream_status(item: &serde_json::Value) -> StreamStatus {
+    let details = &item["liveStreamingDetails"];
+    if details["actualEndTime"].is_string() {
+        StreamStatus::Ended
+    } else if details["activeLiveChatId"].is_string() {
+        StreamStatus::Live
+    } else if item["snippet"]["liveBroadcastContent"] == "upcoming" {
+        StreamStatus::Upcoming
+    } else {
+        StreamStatus::NoChat
+    }
+}
+
+// The first of a channel's recent uploads that is live with a chat
+fn live_video_in(items: &[serde_json::Value]) -> Option<String> {
+    for item in items {
+        match stream_status(item) {
+            StreamStatus::Live => return item["id"].as_str().map(String::from),
+            StreamStatus::Upcoming => tracing::debug!(
+                "Upcoming stream {} scheduled for {}",
+                item["id"],
+                item["liveStreamingDetails"]["scheduledStartTime"]
+            ),
+            StreamStatus::Ended | StreamStatus::NoChat => {}
+        }
+    }
+    None
 }
 
 fn parse_youtube_timestamp(timestamp: &str) -> u64 {
@@ -31,14 +199,116 @@ fn parse_youtube_timestamp(timestamp: &str) -> u64 {
         Err(e) => {
             tracing::warn!("Failed to parse timestamp '{}': {}", timestamp, e);
             // Use current time as fallback
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs()
+            unix_now()
         }
     }
 }
 
+// Error body returned by the YouTube Data API, e.g.
+// {"error": {"code": 403, "message": "...", "errors": [{"reason": "quotaExceeded", ...}]}}
+#[derive(Debug, Deserialize)]
+struct ApiErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    #[serde(default)]
+    code: u16,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    errors: Vec<ApiErrorDetail>,
+    #[serde(default)]
+    details: Vec<ApiErrorInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiErrorDetail {
+    #[serde(default)]
+    reason: String,
+}
+
+// google.rpc.ErrorInfo, which carries reasons like API_KEY_INVALID
+#[derive(Debug, Deserialize)]
+struct ApiErrorInfo {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+impl ApiError {
+    fn reason(&self) -> &str {
+        self.errors
+            .iter()
+            .map(|e| e.reason.as_str())
+            .chain(self.details.iter().filter_map(|d| d.reason.as_deref()))
+            .find(|r| !r.is_empty())
+            .unwrap_or("")
+    }
+
+    fn into_app_error(self, status: StatusCode) -> AppError {
+        match self.reason() {
+            "quotaExceeded" | "dailyLimitExceeded" => AppError::QuotaExceeded,
+            "rateLimitExceeded" | "userRateLimitExceeded" => AppError::RateLimitExceeded,
+            "liveChatEnded" => AppError::LiveChatEnded,
+            "liveChatDisabled" => AppError::LiveChatDisabled,
+            "liveChatNotFound" => AppError::LiveChatNotFound,
+            "keyInvalid" | "keyExpired" | "API_KEY_INVALID" | "API_KEY_EXPIRED" => {
+                AppError::InvalidApiKey
+            }
+            "accessNotConfigured" | "SERVICE_DISABLED" => AppError::ApiNotEnabled,
+            "forbidden" => AppError::Forbidden(self.message),
+            _ if status == StatusCode::TOO_MANY_REQUESTS => AppError::RateLimitExceeded,
+            _ if status.is_server_error() => AppError::ServerError(status.as_u16()),
+            reason => AppError::YouTubeApi {
+                status: if self.code != 0 {
+                    self.code
+                } else {
+                    status.as_u16()
+                },
+                reason: if reason.is_empty() {
+                    "unknown".to_string()
+                } else {
+                    reason.to_string()
+                },
+                message: self.message,
+            },
+        }
+    }
+}
+
+// Send a GET request to the YouTube Data API and decode the JSON body,
+// turning error responses into the matching `AppError`
+async fn get_json(
+    client: &Client,
+    url: &str,
+    endpoint: Endpoint,
+    quota: Option<&QuotaTracker>,
+) -> Result<serde_json::Value> {
+    if let Some(quota) = quota {
+        quota.record(endpoint);
+    }
+
+    let response = client.get(url).send().await.map_err(AppError::Http)?;
+    let status = response.status();
+    let body = response.text().await.map_err(AppError::Http)?;
+
+    if let Ok(error_response) = serde_json::from_str::<ApiErrorResponse>(&body) {
+        return Err(error_response.error.into_app_error(status).into());
+    }
+
+    if !status.is_success() {
+        return Err(if status.is_server_error() {
+            AppError::ServerError(status.as_u16())
+        } else {
+            AppError::YouTube(format!("HTTP {}: {}", status.as_u16(), body))
+        }
+        .into());
+    }
+
+    Ok(serde_json::from_str(&body).map_err(AppError::Json)?)
+}
+
 impl ChatMonitor {
     pub fn new(video_id: &str, api_key: &str) -> Result<Self> {
         if api_key.is_empty() {
@@ -49,45 +319,336 @@ impl ChatMonitor {
             client: Client::new(),
             video_id: video_id.to_string(),
             api_key: api_key.to_string(),
+            live_chat_id: None,
             next_page_token: None,
+            pending_page_token: None,
+            pending: VecDeque::new(),
+            seen_ids: VecDeque::new(),
+            seen_set: HashSet::new(),
+            state_file: None,
+            last_saved: None,
             poll_interval_ms: 3000,
             last_processed_time: 0,
+            backlog: BacklogPolicy::default(),
+            discard_next_page: false,
+            connected_once: false,
+            max_retries: 10,
+            backoff: Backoff::new(
+                Duration::from_millis(RETRY_BASE_DELAY_MS),
+                Duration::from_millis(60_000),
+            ),
+            quota: None,
+            recorder: None,
         })
     }
 
+    pub fn set_quota_tracker(&mut self, quota: QuotaTracker) {
+        self.quota = Some(quota);
+    }
+
+    /// Record every raw chat item and connection event
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Persist the chat position to `path`, resuming from it if it was saved
+    /// for the same video
+    pub fn set_state_file(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+
+        if let Some(state) = ChatState::load(&path) {
+            if state.video_id == self.video_id {
+                tracing::info!(
+                    "Resuming chat position from {:?} ({} seen messages)",
+                    path,
+                    state.seen_ids.len()
+                );
+                self.restore(state);
+            } else {
+                tracing::info!(
+                    "State file {:?} is for video {}, starting fresh",
+                    path,
+                    state.video_id
+                );
+            }
+        }
+
+        self.state_file = Some(path);
+    }
+
+    fn restore(&mut self, state: ChatState) {
+        self.live_chat_id = state.live_chat_id;
+        self.next_page_token = state.next_page_token;
+        // The backlog was dealt with in the run that saved the state
+        self.connected_once = true;
+        for id in state.seen_ids {
+            self.mark_seen(id);
+        }
+    }
+
+    /// Ignore messages published before now, even when resuming from a state file
+    pub fn set_skip_backlog(&mut self, skip: bool) {
+        if skip {
+            self.last_processed_time = unix_now();
+        }
+    }
+
+    /// How to treat the chat history returned when connecting to a chat: messages
+    /// older than `max_age_secs` at connect time are ignored, and with
+    /// `skip_initial_page` the whole first page is discarded.
+    pub fn set_backlog_policy(&mut self, max_age_secs: u64, skip_initial_page: bool) {
+        self.backlog = BacklogPolicy {
+            max_age_secs,
+            skip_initial_page,
+        };
+    }
+
     pub fn set_poll_interval(&mut self, ms: u64) {
         self.poll_interval_ms = ms;
     }
 
-    pub async fn next_message(&mut self) -> Result<Option<ChatMessage>> {
-        if self.next_page_token.is_none() {
-            self.initialize_chat().await?
-        }
+    pub fn set_retry_policy(&mut self, max_retries: u32, max_delay_ms: u64) {
+        self.max_retries = max_retries;
+        self.backoff = Backoff::new(
+            Duration::from_millis(RETRY_BASE_DELAY_MS),
+            Duration::from_millis(max_delay_ms.max(RETRY_BASE_DELAY_MS)),
+        );
+    }
 
+    /// Wait for the next chat message. Returns `None` once the live chat has ended.
+    pub async fn next_message(&mut self) -> Result<Option<ChatMessage>> {
         loop {
-            let messages = self.fetch_messages().await?;
+            if let Some(message) = self.pending.pop_front() {
+                self.mark_seen(message.id.clone());
+                // Saved when the page is done, and now and then while reading a long one
+                let due = self
+                    .last_saved
+                    .is_none_or(|saved| saved.elapsed() >= STATE_SAVE_INTERVAL);
+                if self.pending.is_empty() || due {
+                    self.save_state().await;
+                }
+                return Ok(Some(message));
+            }
+
+            if self.live_chat_id.is_none() {
+                match self.initialize_chat().await {
+                    Ok(()) => self.backoff.reset(),
+                    Err(e) => {
+                        self.wait_before_retry(e).await?;
+                        continue;
+                    }
+                }
+            }
+
+            let request_page_token = self.next_page_token.clone();
+            let messages = match self.fetch_messages().await {
+                Ok(messages) => {
+                    self.backoff.reset();
+                    messages
+                }
+                Err(e) if matches!(e.downcast_ref(), Some(AppError::LiveChatEnded)) => {
+                    tracing::info!("Live chat has ended");
+                    self.record_event("chat ended");
+                    return Ok(None);
+                }
+                Err(e) => {
+                    self.wait_before_retry(e).await?;
+                    continue;
+                }
+            };
+
+            if self.discard_next_page {
+                self.discard_next_page = false;
+                if !messages.is_empty() {
+                    tracing::info!("Skipped {} backlog messages", messages.len());
+                }
+                for message in messages {
+                    self.mark_seen(message.id);
+                }
+                self.save_state().await;
+                continue;
+            }
 
             if !messages.is_empty() {
-                return Ok(Some(messages[0].clone()));
+                self.pending_page_token = request_page_token;
+                self.pending.extend(messages);
+                continue;
             }
+            self.save_state().await;
+
+            let interval_ms = match &self.quota {
+                Some(quota) => {
+                    quota.poll_interval_ms(self.poll_interval_ms, Endpoint::LiveChatMessages)
+                }
+                None => self.poll_interval_ms,
+            };
+            sleep(Duration::from_millis(interval_ms)).await;
+        }
+    }
+
+    // Sleep before the next attempt, or give up if the error is fatal or retries ran out
+    async fn wait_before_retry(&mut self, err: anyhow::Error) -> Result<()> {
+        if !error::is_retryable(&err) {
+            return Err(err);
+        }
 
-            sleep(Duration::from_millis(self.poll_interval_ms)).await;
+        if self.backoff.attempts() >= self.max_retries {
+            tracing::error!("Giving up after {} retries", self.max_retries);
+            return Err(err);
         }
+
+        let delay = self.backoff.next_delay();
+        tracing::warn!(
+            "YouTube API request failed: {}. Retrying in {:.1}s ({}/{})",
+            err,
+            delay.as_secs_f64(),
+            self.backoff.attempts(),
+            self.max_retries
+        );
+        sleep(delay).await;
+        Ok(())
     }
 
     async fn initialize_chat(&mut self) -> Result<()> {
+        let chat_id = Self::get_live_chat_id(
+            &self.client,
+            &self.video_id,
+            &self.api_key,
+            self.quota.as_ref(),
+        )
+        .await?
+        .ok_or_else(|| AppError::YouTube("Live chat not available".to_string()))?;
+
+        self.record_event(&format!("connected to live chat {}", chat_id));
+        self.live_chat_id = Some(chat_id);
+        self.next_page_token = None;
+
+        // The first page of a freshly connected chat is recent history, not new messages
+        let (cutoff, discard_page) =
+            self.backlog
+                .on_connect(self.last_processed_time, unix_now(), self.connected_once);
+        self.last_processed_time = cutoff;
+        self.discard_next_page |= discard_page;
+        self.connected_once = true;
+
+        Ok(())
+    }
+
+    fn record_event(&self, event: &str) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_event(&self.video_id, event);
+        }
+    }
+
+    fn mark_seen(&mut self, id: String) {
+        if self.seen_set.insert(id.clone()) {
+            self.seen_ids.push_back(id);
+            if self.seen_ids.len() > MAX_SEEN_IDS {
+                if let Some(oldest) = self.seen_ids.pop_front() {
+                    self.seen_set.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    async fn save_state(&mut self) {
+        let Some(path) = self.state_file.clone() else {
+            return;
+        };
+        self.last_saved = Some(Instant::now());
+
+        let state = self.chat_state();
+        let result = match tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || state.save(&path)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to save chat state to {:?}: {}", path, e);
+        }
+    }
+
+    fn chat_state(&self) -> ChatState {
+        // While fetched messages are still pending, save the token of the page they
+        // came from so they are fetched again (and deduplicated) after a restart
+        ChatState {
+            video_id: self.video_id.clone(),
+            live_chat_id: self.live_chat_id.clone(),
+            next_page_token: if self.pending.is_empty() {
+                self.next_page_token.clone()
+            } else {
+                self.pending_page_token.clone()
+            },
+            seen_ids: self.seen_ids.iter().cloned().collect(),
+        }
+    }
+
+    /// Resolve a target to the ID of the video whose chat should be read,
+    /// looking up the live stream if the target is a channel
+    pub async fn resolve_video_id(
+        client: &Client,
+        target: &Target,
+        api_key: &str,
+        quota: Option<&QuotaTracker>,
+    ) -> Result<String> {
+        match target {
+            Target::Video(video_id) => Ok(video_id.clone()),
+            _ => {
+                let channel_id = Self::resolve_channel_id(client, target, api_key, quota).await?;
+                Self::find_live_video_id_by_channel(client, &channel_id, api_key, quota).await
+            }
+        }
+    }
+
+    pub async fn resolve_channel_id(
+        client: &Client,
+        target: &Target,
+        api_key: &str,
+        quota: Option<&QuotaTracker>,
+    ) -> Result<String> {
+        let channel_id = match target {
+            Target::ChannelId(channel_id) => return Ok(channel_id.clone()),
+            Target::Handle(handle) => {
+                Self::get_channel_id(client, "forHandle", handle, api_key, quota).await?
+            }
+            Target::Username(username) => {
+                // Most names typed today are handles without the @, so fall back to that
+                match Self::get_channel_id(client, "forUsername", username, api_key, quota).await? {
+                    Some(channel_id) => Some(channel_id),
+                    None => {
+                        Self::get_channel_id(client, "forHandle", username, api_key, quota).await?
+                    }
+                }
+            }
+            Target::Video(video_id) => {
+                return Err(
+                    AppError::YouTube(format!("{} is a video, not a channel", video_id)).into(),
+                )
+            }
+        };
+
+        channel_id
+            .ok_or_else(|| AppError::YouTube(format!("Channel not found for {:?}", target)).into())
+    }
+
+    /// Look up the active live chat of a video. Returns `None` if the stream
+    /// has not started yet.
+    pub async fn get_live_chat_id(
+        client: &Client,
+        video_id: &str,
+        api_key: &str,
+        quota: Option<&QuotaTracker>,
+    ) -> Result<Option<String>> {
         let url = format!(
             "https://www.googleapis.com/youtube/v3/videos?part=liveStreamingDetails&id={}&key={}",
-            self.video_id, self.api_key
+            video_id, api_key
         );
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
+        let response = get_json(client, &url, Endpoint::Videos, quota).await?;
 
         let items = response["items"]
             .as_array()
@@ -99,32 +660,147 @@ impl ChatMonitor {
             );
         }
 
-        // Store the live chat ID
-        let chat_id = items[0]["liveStreamingDetails"]["activeLiveChatId"]
-            .as_str()
-            .ok_or_else(|| AppError::YouTube("Live chat not available".to_string()))?
-            .to_string();
+        let details = &items[0]["liveStreamingDetails"];
+        if details["actualEndTime"].is_string() {
+            return Err(AppError::LiveChatEnded.into());
+        }
 
-        // Store the chat ID in the video_id field temporarily
-        self.video_id = chat_id;
-        self.next_page_token = Some(String::new());
-        Ok(())
+        Ok(details["activeLiveChatId"].as_str().map(String::from))
+    }
+
+    /// Wait until the target has a live stream with an active chat and return
+    /// its video ID. Channels are checked through their uploads playlist, which
+    /// costs far less quota than a search.
+    pub async fn wait_for_live_video(
+        client: &Client,
+        target: &Target,
+        api_key: &str,
+        quota: Option<&QuotaTracker>,
+        poll_interval: Duration,
+    ) -> Result<String> {
+        let channel_id = match target {
+            Target::Video(_) => None,
+            _ => Some(Self::resolve_channel_id(client, target, api_key, quota).await?),
+        };
+
+        tracing::info!("Waiting for {:?} to go live", target);
+        loop {
+            let result = match (target, &channel_id) {
+                (Target::Video(video_id), _) => {
+                    Self::check_video_is_live(client, video_id, api_key, quota).await
+                }
+                (_, Some(channel_id)) => {
+                    Self::find_live_video_in_uploads(client, channel_id, api_key, quota).await
+                }
+                (_, None) => unreachable!("channel targets are resolved above"),
+            };
+
+            match result {
+                Ok(Some(video_id)) => {
+                    tracing::info!("Stream is live: {}", video_id);
+                    return Ok(video_id);
+                }
+                Ok(None) => {}
+                Err(e) if error::is_retryable(&e) => {
+                    tracing::warn!("Failed to check for a live stream: {}", e);
+                }
+                Err(e) => return Err(e),
+            }
+
+            sleep(poll_interval).await;
+        }
+    }
+
+    // The video ID once it is live, or `None` while the stream is still upcoming.
+    // Anything else can never go live, so waiting for it would never end.
+    async fn check_video_is_live(
+        client: &Client,
+        video_id: &str,
+        api_key: &str,
+        quota: Option<&QuotaTracker>,
+    ) -> Result<Option<String>> {
+        let url = format!(
+            "https://www.googleapis.com/youtube/v3/videos?part=snippet,liveStreamingDetails&id={}&key={}",
+            video_id, api_key
+        );
+
+        let response = get_json(client, &url, Endpoint::Videos, quota).await?;
+        let item = response["items"]
+            .as_array()
+            .and_then(|items| items.first())
+            .ok_or_else(|| AppError::YouTube(format!("Video {} not found", video_id)))?;
+
+        match stream_status(item) {
+            StreamStatus::Live => Ok(Some(video_id.to_string())),
+            StreamStatus::Upcoming => Ok(None),
+            StreamStatus::Ended => Err(AppError::LiveChatEnded.into()),
+            StreamStatus::NoChat => Err(AppError::YouTube(format!(
+                "Video {} is not an upcoming or live stream with a chat",
+                video_id
+            ))
+            .into()),
+        }
+    }
+
+    // Check the most recent uploads of a channel for a stream with an active chat
+    async fn find_live_video_in_uploads(
+        client: &Client,
+        channel_id: &str,
+        api_key: &str,
+        quota: Option<&QuotaTracker>,
+    ) -> Result<Option<String>> {
+        // The uploads playlist of channel UCxxxx is UUxxxx
+        let playlist_id = format!("UU{}", channel_id.strip_prefix("UC").unwrap_or(channel_id));
+        let url = format!(
+            "https://www.googleapis.com/youtube/v3/playlistItems?part=contentDetails&playlistId={}&maxResults=10&key={}",
+            playlist_id, api_key
+        );
+
+        let response = get_json(client, &url, Endpoint::PlaylistItems, quota).await?;
+
+        let video_ids: Vec<&str> = response["items"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item["contentDetails"]["videoId"].as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if video_ids.is_empty() {
+            return Ok(None);
+        }
+
+        let url = format!(
+            "https://www.googleapis.com/youtube/v3/videos?part=snippet,liveStreamingDetails&id={}&key={}",
+            video_ids.join(","),
+            api_key
+        );
+
+        let response = get_json(client, &url, Endpoint::Videos, quota).await?;
+
+        Ok(response["items"]
+            .as_array()
+            .and_then(|items| live_video_in(items)))
     }
 
     pub async fn find_live_video_id_by_channel(
         client: &Client,
         channel_id: &str,
         api_key: &str,
+        quota: Option<&QuotaTracker>,
     ) -> Result<String> {
         tracing::info!("Searching for live stream for channel: {}", channel_id);
 
-        // First, check if the input is a custom channel name (without the UC prefix)
-        // If so, we need to get the actual channel ID first
-        let actual_channel_id = if !channel_id.starts_with("UC") {
-            Self::get_channel_id_by_username(client, channel_id, api_key).await?
-        } else {
-            channel_id.to_string()
-        };
+        // The input may also be a handle, username or URL rather than a UC… ID
+        let actual_channel_id = Self::resolve_channel_id(
+            client,
+            &Target::from_channel_arg(channel_id),
+            api_key,
+            quota,
+        )
+        .await?;
 
         // Now search for live broadcasts on this channel
         let url = format!(
@@ -132,12 +808,7 @@ impl ChatMonitor {
             actual_channel_id, api_key
         );
 
-        let response = client
-            .get(&url)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
+        let response = get_json(client, &url, Endpoint::Search, quota).await?;
 
         let items = response["items"]
             .as_array()
@@ -162,34 +833,29 @@ impl ChatMonitor {
         Ok(video_id)
     }
 
-    async fn get_channel_id_by_username(
+    // Look up a channel by `forUsername` or `forHandle`
+    async fn get_channel_id(
         client: &Client,
-        username: &str,
+        lookup: &str,
+        name: &str,
         api_key: &str,
-    ) -> Result<String> {
-        tracing::info!("Looking up channel ID for username: {}", username);
-
-        let url = format!(
-            "https://www.googleapis.com/youtube/v3/channels?part=id&forUsername={}&key={}",
-            username, api_key
-        );
+        quota: Option<&QuotaTracker>,
+    ) -> Result<Option<String>> {
+        tracing::info!("Looking up channel ID ({}): {}", lookup, name);
 
-        let response = client
-            .get(&url)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
+        let url = reqwest::Url::parse_with_params(
+            "https://www.googleapis.com/youtube/v3/channels",
+            &[("part", "id"), (lookup, name), ("key", api_key)],
+        )
+        .map_err(|e| AppError::YouTube(format!("Invalid channel name {}: {}", name, e)))?;
 
-        let items = response["items"]
-            .as_array()
-            .ok_or_else(|| AppError::YouTube("Invalid API response".to_string()))?;
+        let response = get_json(client, url.as_str(), Endpoint::Channels, quota).await?;
 
-        if items.is_empty() {
-            return Err(
-                AppError::YouTube(format!("Channel not found for username {}", username)).into(),
-            );
-        }
+        // The API omits `items` entirely when nothing matches
+        let items = match response["items"].as_array() {
+            Some(items) if !items.is_empty() => items,
+            _ => return Ok(None),
+        };
 
         let channel_id = items[0]["id"]
             .as_str()
@@ -198,13 +864,17 @@ impl ChatMonitor {
 
         tracing::info!("Found channel ID: {}", channel_id);
 
-        Ok(channel_id)
+        Ok(Some(channel_id))
     }
 
     async fn fetch_messages(&mut self) -> Result<Vec<ChatMessage>> {
+        let Some(live_chat_id) = &self.live_chat_id else {
+            return Ok(vec![]);
+        };
+
         let mut url = format!(
             "https://www.googleapis.com/youtube/v3/liveChat/messages?part=snippet,authorDetails&liveChatId={}&key={}",
-            self.video_id, self.api_key
+            live_chat_id, self.api_key
         );
 
         if let Some(token) = &self.next_page_token {
@@ -213,31 +883,47 @@ impl ChatMonitor {
             }
         }
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
+        let response = get_json(
+            &self.client,
+            &url,
+            Endpoint::LiveChatMessages,
+            self.quota.as_ref(),
+        )
+        .await?;
 
         self.next_page_token = response["nextPageToken"].as_str().map(String::from);
+        if self.next_page_token.is_none() {
+            // Without a page token the chat has to be looked up again
+            self.live_chat_id = None;
+        }
+
+        // Once the chat goes offline, the remaining messages are delivered
+        // before the stream is reported as ended
+        let offline = response["offlineAt"].is_string();
 
         let items = match response["items"].as_array() {
             Some(items) => items,
+            None if offline => return Err(AppError::LiveChatEnded.into()),
             None => return Ok(vec![]),
         };
 
         let mut messages = Vec::new();
         for item in items {
+            if let Some(recorder) = &self.recorder {
+                recorder.record_raw(&self.video_id, item);
+            }
+
             if let (Some(id), Some(author), Some(text), Some(timestamp)) = (
                 item["id"].as_str(),
                 item["authorDetails"]["displayName"].as_str(),
                 item["snippet"]["displayMessage"].as_str(),
                 item["snippet"]["publishedAt"].as_str(),
             ) {
-                let ts_value = parse_youtube_timestamp(timestamp);
-                if ts_value <= self.last_processed_time {
+                if self.seen_set.contains(id) {
+                    continue;
+                }
+
+                if is_backlog(parse_youtube_timestamp(timestamp), self.last_processed_time) {
                     continue;
                 }
 
@@ -246,16 +932,239 @@ impl ChatMonitor {
                     author: author.to_string(),
                     text: text.to_string(),
                     timestamp: timestamp.to_string(),
+                    source: String::new(),
+                    roles: author_roles(&item["authorDetails"]),
+                    amount: item["snippet"]["superChatDetails"]["amountDisplayString"]
+                        .as_str()
+                        .or_else(|| {
+                            item["snippet"]["superStickerDetails"]["amountDisplayString"].as_str()
+                        })
+                        .map(String::from),
                 });
             }
         }
 
-        messages.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        if offline && messages.is_empty() {
+            return Err(AppError::LiveChatEnded.into());
+        }
+
+        messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
 
-        if let Some(latest_msg) = messages.first() {
+        if let Some(latest_msg) = messages.last() {
             self.last_processed_time = parse_youtube_timestamp(&latest_msg.timestamp);
         }
 
         Ok(messages)
     }
 }
+
+#[async_trait]
+impl ChatSource for ChatMonitor {
+    async fn next_message(&mut self) -> Result<Option<ChatMessage>> {
+        ChatMonitor::next_message(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_error(status: u16, body: &str) -> AppError {
+        let response: ApiErrorResponse = serde_json::from_str(body).unwrap();
+        response
+            .error
+            .into_app_error(StatusCode::from_u16(status).unwrap())
+    }
+
+    #[test]
+    fn maps_error_reasons() {
+        let with_reason = |reason: &str| {
+            format!(
+                r#"{{"error": {{"code": 403, "message": "msg", "errors": [{{"reason": "{}"}}]}}}}"#,
+                reason
+            )
+        };
+        let cases = [
+            ("quotaExceeded", AppError::QuotaExceeded),
+            ("dailyLimitExceeded", AppError::QuotaExceeded),
+            ("rateLimitExceeded", AppError::RateLimitExceeded),
+            ("userRateLimitExceeded", AppError::RateLimitExceeded),
+            ("liveChatEnded", AppError::LiveChatEnded),
+            ("liveChatDisabled", AppError::LiveChatDisabled),
+            ("liveChatNotFound", AppError::LiveChatNotFound),
+            ("keyInvalid", AppError::InvalidApiKey),
+            ("keyExpired", AppError::InvalidApiKey),
+            ("accessNotConfigured", AppError::ApiNotEnabled),
+            ("forbidden", AppError::Forbidden("msg".to_string())),
+            (
+                "somethingNew",
+                AppError::YouTubeApi {
+                    status: 403,
+                    reason: "somethingNew".to_string(),
+                    message: "msg".to_string(),
+                },
+            ),
+        ];
+
+        for (reason, expected) in cases {
+            assert_eq!(
+                app_error(403, &with_reason(reason)).to_string(),
+                expected.to_string(),
+                "{}",
+                reason
+            );
+        }
+    }
+
+    #[test]
+    fn falls_back_to_details_and_status() {
+        // Newer responses carry the reason in google.rpc.ErrorInfo details
+        let details = r#"{"error": {"code": 400, "message": "bad key",
+            "details": [{"@type": "type.googleapis.com/google.rpc.ErrorInfo", "reason": "API_KEY_INVALID"}]}}"#;
+        assert!(matches!(app_error(400, details), AppError::InvalidApiKey));
+
+        let no_reason = r#"{"error": {"message": "try later"}}"#;
+        assert!(matches!(
+            app_error(429, no_reason),
+            AppError::RateLimitExceeded
+        ));
+        assert!(matches!(
+            app_error(503, no_reason),
+            AppError::ServerError(503)
+        ));
+        assert_eq!(
+            app_error(400, no_reason).to_string(),
+            "YouTube API error (HTTP 400, unknown): try later"
+        );
+    }
+
+    fn state(video_id: &str, seen_ids: &[&str]) -> ChatState {
+        ChatState {
+            video_id: video_id.to_string(),
+            live_chat_id: Some("chat".to_string()),
+            next_page_token: Some("page".to_string()),
+            seen_ids: seen_ids.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn chat_state_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        assert_eq!(ChatState::load(&path), None);
+
+        let saved = state("video", &["a", "b"]);
+        saved.save(&path).unwrap();
+        assert_eq!(ChatState::load(&path), Some(saved));
+
+        fs::write(&path, "{\"video_id\": ").unwrap();
+        assert_eq!(ChatState::load(&path), None);
+    }
+
+    #[test]
+    fn resumes_only_the_same_video() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        state("other", &["a"]).save(&path).unwrap();
+
+        let mut monitor = ChatMonitor::new("video", "key").unwrap();
+        monitor.set_state_file(&path);
+        assert_eq!(monitor.live_chat_id, None);
+        assert!(!monitor.connected_once);
+        assert!(monitor.seen_set.is_empty());
+
+        state("video", &["a", "b"]).save(&path).unwrap();
+        let mut monitor = ChatMonitor::new("video", "key").unwrap();
+        monitor.set_state_file(&path);
+        assert_eq!(monitor.live_chat_id.as_deref(), Some("chat"));
+        assert_eq!(monitor.next_page_token.as_deref(), Some("page"));
+        assert!(monitor.connected_once);
+        assert_eq!(monitor.chat_state(), state("video", &["a", "b"]));
+    }
+
+    #[test]
+    fn skip_backlog_applies_to_a_resumed_chat() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        state("video", &[]).save(&path).unwrap();
+
+        let mut monitor = ChatMonitor::new("video", "key").unwrap();
+        monitor.set_state_file(&path);
+        monitor.set_skip_backlog(false);
+        assert_eq!(monitor.last_processed_time, 0);
+
+        let before = unix_now();
+        monitor.set_skip_backlog(true);
+        assert!(monitor.last_processed_time >= before);
+        assert!(monitor.connected_once);
+    }
+
+    #[test]
+    fn remembers_a_bounded_set_of_seen_ids() {
+        let mut monitor = ChatMonitor::new("video", "key").unwrap();
+        monitor.mark_seen("a".to_string());
+        monitor.mark_seen("a".to_string());
+        assert_eq!(monitor.seen_ids.len(), 1);
+
+        for i in 0..MAX_SEEN_IDS {
+            monitor.mark_seen(i.to_string());
+        }
+        assert_eq!(monitor.seen_ids.len(), MAX_SEEN_IDS);
+        assert_eq!(monitor.seen_set.len(), MAX_SEEN_IDS);
+        assert!(!monitor.seen_set.contains("a"));
+        assert!(monitor.seen_set.contains("0"));
+    }
+
+    #[test]
+    fn picks_the_live_upload() {
+        let upcoming = serde_json::json!({
+            "id": "upcoming",
+            "snippet": {"liveBroadcastContent": "upcoming"},
+            "liveStreamingDetails": {"scheduledStartTime": "2024-01-01T00:00:00Z"}
+        });
+        let ended = serde_json::json!({
+            "id": "ended",
+            "snippet": {"liveBroadcastContent": "none"},
+            "liveStreamingDetails": {"activeLiveChatId": "old", "actualEndTime": "2024-01-01T01:00:00Z"}
+        });
+        let live = serde_json::json!({
+            "id": "live",
+            "snippet": {"liveBroadcastContent": "live"},
+            "liveStreamingDetails": {"activeLiveChatId": "chat"}
+        });
+        let video = serde_json::json!({"id": "video", "snippet": {"liveBroadcastContent": "none"}});
+
+        assert_eq!(stream_status(&upcoming), StreamStatus::Upcoming);
+        assert_eq!(stream_status(&ended), StreamStatus::Ended);
+        assert_eq!(stream_status(&live), StreamStatus::Live);
+        assert_eq!(stream_status(&video), StreamStatus::NoChat);
+
+        let items = [upcoming.clone(), ended.clone(), video, live];
+        assert_eq!(live_video_in(&items).as_deref(), Some("live"));
+        assert_eq!(live_video_in(&[upcoming, ended]), None);
+        assert_eq!(live_video_in(&[]), None);
+    }
+
+    #[test]
+    fn tells_backlog_from_new_messages() {
+        let connected_at = 1_000;
+
+        // By default only messages from the last 30 seconds are read
+        let policy = BacklogPolicy::default();
+        let (cutoff, discard_page) = policy.on_connect(0, connected_at, false);
+        assert_eq!((cutoff, discard_page), (970, false));
+        assert!(is_backlog(969, cutoff));
+        assert!(!is_backlog(970, cutoff));
+
+        // --skip-backlog moved the cutoff past the allowed age
+        assert_eq!(policy.on_connect(995, connected_at, false).0, 995);
+
+        let policy = BacklogPolicy {
+            max_age_secs: 0,
+            skip_initial_page: true,
+        };
+        assert_eq!(policy.on_connect(0, connected_at, false), (1_000, true));
+        // A reconnect or resumed chat keeps its first page
+        assert_eq!(policy.on_connect(0, connected_at, true), (1_000, false));
+    }
+}